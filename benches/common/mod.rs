@@ -0,0 +1,88 @@
+#![allow(dead_code)]
+
+use std::{
+    env::temp_dir,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Shape {
+    /// Every file directly inside the root.
+    Wide,
+    /// A single chain of directories with the files spread along it.
+    Deep,
+    /// Four sub directories per level, four levels down, files in every one.
+    Mixed,
+}
+
+impl Shape {
+    pub const ALL: [Shape; 3] = [Shape::Wide, Shape::Deep, Shape::Mixed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Wide => "wide",
+            Shape::Deep => "deep",
+            Shape::Mixed => "mixed",
+        }
+    }
+
+    fn dirs(&self, root: &Path) -> Vec<PathBuf> {
+        match self {
+            Shape::Wide => vec![root.to_path_buf()],
+            Shape::Deep => (0..32)
+                .scan(root.to_path_buf(), |dir, depth| {
+                    *dir = dir.join(format!("level{depth}"));
+                    Some(dir.clone())
+                })
+                .collect(),
+            Shape::Mixed => {
+                let mut dirs = vec![root.to_path_buf()];
+                let mut level = vec![root.to_path_buf()];
+                for _ in 0..4 {
+                    level = level
+                        .iter()
+                        .flat_map(|dir| (0..4).map(move |i| dir.join(format!("dir{i}"))))
+                        .collect();
+                    dirs.extend(level.iter().cloned());
+                }
+                dirs
+            }
+        }
+    }
+}
+
+/// A synthetic tree of empty files in the temp dir, removed on drop.
+pub struct Fixture {
+    pub root: PathBuf,
+    pub files: Vec<PathBuf>,
+}
+
+impl Fixture {
+    pub fn new(name: &str, shape: Shape, file_count: usize) -> Self {
+        let root = temp_dir().join(format!("irminsul_bench_{name}_{}", shape.name()));
+        let _ = fs::remove_dir_all(&root);
+        let dirs = shape.dirs(&root);
+        let files = (0..file_count)
+            .map(|i| dirs[i % dirs.len()].join(format!("file{i}.txt")))
+            .collect::<Vec<_>>();
+        dirs.iter().for_each(|dir| fs::create_dir_all(dir).unwrap());
+        files.iter().for_each(|file| {
+            fs::File::create(file).unwrap();
+        });
+        Self { root, files }
+    }
+
+    /// Rewrites one of the files so its mtime moves.
+    pub fn touch(&self, index: usize) {
+        let mut file = fs::File::create(&self.files[index % self.files.len()]).unwrap();
+        let _ = writeln!(file, "{index}");
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
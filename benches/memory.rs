@@ -0,0 +1,62 @@
+//! Reports the heap held by a built treemap, rather than timing anything.
+
+use irminsul::modules::node::Node;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+mod common;
+use common::{Fixture, Shape};
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LIVE.fetch_add(new_size, Ordering::Relaxed);
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    // `cargo bench` passes `--bench`; anything else is a name filter.
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    for (shape, files) in [(Shape::Mixed, 100_000), (Shape::Deep, 100_000)] {
+        let name = format!("memory/{}_{files}", shape.name());
+        if filter.as_ref().is_some_and(|filter| !name.contains(filter)) {
+            continue;
+        }
+        let fixture = Fixture::new("memory", shape, files);
+        let node = Node {
+            root: fixture.root.clone(),
+            path_pattern: Some("**/*.txt".to_owned()),
+            exec: None,
+            ..Default::default()
+        };
+        let before = LIVE.load(Ordering::Relaxed);
+        let map = node.build_treemap();
+        let held = LIVE.load(Ordering::Relaxed).saturating_sub(before);
+        println!(
+            "{name:<24} {:>10.2} MiB  {:>6} B/file",
+            held as f64 / (1024.0 * 1024.0),
+            held / files
+        );
+        drop(map);
+    }
+}
@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkGroup, Criterion};
+use criterion::{measurement::WallTime, BenchmarkId};
+#[cfg(all(feature = "poll", feature = "notify"))]
+use irminsul::modules::modes::mix::Mix;
+#[cfg(feature = "notify")]
+use irminsul::modules::modes::notify::Notify;
+#[cfg(feature = "poll")]
+use irminsul::modules::modes::poll::Poll as PollMode;
+use irminsul::modules::{modes::Poll, node::Node};
+
+mod common;
+use common::{Fixture, Shape};
+
+const FILES: usize = 1000;
+
+fn bench_mode<M: Poll>(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    mode: M,
+    fixture: &Fixture,
+    shape: Shape,
+) {
+    let mut map = Node {
+        root: fixture.root.clone(),
+        path_pattern: Some("**/*.txt".to_owned()),
+        exec: None,
+        ..Default::default()
+    }
+    .build_treemap();
+
+    group.bench_function(
+        BenchmarkId::new(format!("{name}/idle"), shape.name()),
+        |b| b.iter(|| mode.poll(&mut map)),
+    );
+
+    let mut edits = 0;
+    group.bench_function(
+        BenchmarkId::new(format!("{name}/edit"), shape.name()),
+        |b| {
+            b.iter_batched(
+                || {
+                    edits += 1;
+                    fixture.touch(edits);
+                },
+                |_| mode.poll(&mut map),
+                BatchSize::PerIteration,
+            )
+        },
+    );
+}
+
+fn bench_modes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("poll");
+    for shape in Shape::ALL {
+        let fixture = Fixture::new("modes", shape, FILES);
+        #[cfg(feature = "poll")]
+        bench_mode(&mut group, "poll", PollMode::default(), &fixture, shape);
+        #[cfg(feature = "notify")]
+        bench_mode(&mut group, "notify", Notify::default(), &fixture, shape);
+        #[cfg(all(feature = "poll", feature = "notify"))]
+        bench_mode(&mut group, "mix", Mix::default(), &fixture, shape);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_modes);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use irminsul::modules::{
+    modes::{poll::Poll, Poll as PollTrait},
+    node::Node,
+};
+use std::{fs, time::Duration};
+
+mod common;
+use common::{Fixture, Shape};
+
+const FILES: usize = 100_000;
+
+fn bench_poll_100k(c: &mut Criterion) {
+    let fixture = Fixture::new("100k", Shape::Mixed, FILES);
+    let node = Node {
+        root: fixture.root.clone(),
+        path_pattern: Some("**/*.txt".to_owned()),
+        exec: None,
+        ..Default::default()
+    };
+    let mut map = node.build_treemap();
+
+    let mut group = c.benchmark_group("poll_100k");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(20));
+
+    group.bench_function("hierarchical_no_change", |b| {
+        b.iter(|| Poll::default().poll(&mut map))
+    });
+
+    let mut counter = 0;
+    group.bench_function("hierarchical_new_file", |b| {
+        b.iter_batched(
+            || {
+                counter += 1;
+                let dir = fixture.files[counter % FILES].parent().unwrap();
+                fs::File::create(dir.join(format!("new{counter}.txt"))).unwrap();
+            },
+            |_| Poll::default().poll(&mut map),
+            BatchSize::PerIteration,
+        )
+    });
+
+    // Picking up new files without directory mtimes means re-globbing.
+    group.bench_function("rebuild", |b| b.iter(|| node.build_treemap()));
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_poll_100k);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use irminsul::modules::node::Node;
+
+mod common;
+use common::{Fixture, Shape};
+
+const FILES: usize = 1000;
+
+fn bench_build_treemap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_treemap");
+    for shape in Shape::ALL {
+        let fixture = Fixture::new("build", shape, FILES);
+        let node = Node {
+            root: fixture.root.clone(),
+            path_pattern: Some("**/*.txt".to_owned()),
+            exec: None,
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::from_parameter(shape.name()),
+            &node,
+            |b, node| b.iter(|| node.build_treemap()),
+        );
+    }
+    group.finish();
+}
+
+fn bench_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merge");
+    for shape in Shape::ALL {
+        let fixture = Fixture::new("merge", shape, FILES);
+        let even = Node {
+            root: fixture.root.clone(),
+            path_pattern: Some("**/file*[02468].txt".to_owned()),
+            exec: None,
+            ..Default::default()
+        }
+        .build_treemap();
+        let odd = Node {
+            root: fixture.root.clone(),
+            path_pattern: Some("**/file*[13579].txt".to_owned()),
+            exec: None,
+            ..Default::default()
+        }
+        .build_treemap();
+        group.bench_function(BenchmarkId::from_parameter(shape.name()), |b| {
+            b.iter_batched(
+                || (even.clone(), odd.clone()),
+                |(mut even, odd)| {
+                    even.merge(odd);
+                    even
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_build_treemap, bench_merge);
+criterion_main!(benches);
@@ -0,0 +1,4 @@
+#![allow(clippy::needless_return, clippy::vec_box, clippy::result_unit_err)]
+
+pub mod modules;
+pub mod utils;
@@ -1,39 +1,74 @@
-// use modules::modes::{mix::Mix, Poll};
-// use std::{path::PathBuf, thread::sleep, time::Duration};
-// use utils::path_map;
+#![allow(clippy::needless_return)]
 
-pub mod modules;
-pub mod utils;
+use clap::Parser;
+use irminsul::modules::{
+    cli::{Cli, Command},
+    events,
+    output::{self, Style},
+    signals, watch,
+};
+use std::process::ExitCode;
+use stderrlog::ColorChoice;
 
-fn main() {
-    // let mut map = path_map(
-    //     vec![
-    //         r"E:\Projects\watch-rs\src\modules\config.rs",
-    //         r"E:\Projects\watch-rs\src\modules\modes\poll.rs",
-    //         r".\src\utils\treemap.rs",
-    //         r".\src\utils\mod.rs",
-    //         r".\src\utils\pathmap.rs",
-    //         r".\src\main.rs",
-    //         r".\src\modules\mod.rs",
-    //         r".\Readme.md",
-    //         r"test",
-    //         r".\src\modules\modes\mix.rs",
-    //         r"E:\Steam\steamapps\common\Blender",
-    //     ]
-    //     .iter()
-    //     .map(|f| PathBuf::from(f))
-    //     .collect(),
-    // );
-    // loop {
-    //     sleep(Duration::from_millis(200));
-    //     let res = Mix::default().poll(&mut map);
-    //     match res {
-    //         None => (),
-    //         Some(paths) => println!("paths updated {:#?}", paths),
-    //     }
-    // }
-    // for root in map {
-    //     root.display(&"".to_owned());
-    // }
-    // println!("{:#?}", map)
+/// What `irminsul changed` exits with when it fails, 1 meaning files changed.
+const CHANGED_FAILED: u8 = 2;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mut logger = stderrlog::new();
+    // Errors loading the config are logged at the default level, the config's
+    // verbosity only applies once it is loaded.
+    logger.verbosity(log::LevelFilter::Trace);
+    #[cfg(feature = "timestamps")]
+    logger.timestamp(cli.timestamp);
+    let _ = logger.init();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let failure = match cli.command {
+        Some(Command::Changed { .. }) => ExitCode::from(CHANGED_FAILED),
+        _ => ExitCode::FAILURE,
+    };
+    let Ok(mut config) = cli.load_config() else {
+        return failure;
+    };
+    if config.select(&cli.only, &cli.except).is_err() {
+        return failure;
+    }
+    log::set_max_level(match config.verbosity + cli.verbose as usize {
+        0 => log::LevelFilter::Info,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    });
+    output::init(Style {
+        color: ColorChoice::Auto,
+        #[cfg(feature = "timestamps")]
+        timestamp: cli.timestamp,
+        width: config
+            .nodes
+            .iter()
+            .map(|conf_node| conf_node.label().chars().count())
+            .max()
+            .unwrap_or_default(),
+    });
+
+    events::init(cli.json);
+    signals::install();
+    let result = match &cli.command {
+        None | Some(Command::Watch) => watch::watch(&config),
+        Some(Command::Run { names }) => watch::run(&config, names),
+        Some(Command::Tree) => watch::tree(&config),
+        Some(Command::Explain { path }) => watch::explain(&config, path),
+        Some(Command::Changed { since }) => match watch::changed(&config, *since) {
+            Ok(true) => return ExitCode::FAILURE,
+            Ok(false) => Ok(()),
+            Err(()) => Err(()),
+        },
+    };
+    if let Some(signal) = signals::received() {
+        return ExitCode::from(signals::exit_code(signal));
+    }
+    return match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(()) => failure,
+    };
 }
@@ -0,0 +1,244 @@
+use clap::{ArgAction, Parser, Subcommand};
+use log::error;
+use std::path::PathBuf;
+#[cfg(feature = "timestamps")]
+use stderrlog::Timestamp;
+
+use super::{command::quote, config::Config, node::Node};
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// The config to load nodes from.
+    #[arg(short, long, default_value = "irminsul.json", global = true)]
+    pub config: PathBuf,
+    /// Only use the nodes with these names.
+    #[arg(long, value_delimiter = ',', global = true)]
+    pub only: Vec<String>,
+    /// Leave out the nodes with these names.
+    #[arg(long, value_delimiter = ',', global = true)]
+    pub except: Vec<String>,
+    /// Log more, on top of the config's verbosity. Can be repeated.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Prefix log and output lines with a timestamp: sec, ms, us, ns or off.
+    #[cfg(feature = "timestamps")]
+    #[arg(short, long, default_value = "off", global = true)]
+    pub timestamp: Timestamp,
+    /// Take requests on a Unix socket at this path while watching, overriding
+    /// the config's `socket`.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub socket: Option<PathBuf>,
+    /// Save the watched files to this file on exit and compare against it on
+    /// start, overriding the config's `state_file`.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub state_file: Option<PathBuf>,
+    /// Run the nodes whose files changed while irminsul was not running.
+    #[arg(long, global = true)]
+    pub catch_up: bool,
+    /// Print changes and what commands do as JSON lines on stdout, moving
+    /// command output to stderr.
+    #[arg(long, global = true)]
+    pub json: bool,
+    /// Watch paths matching this pattern, relative to the current directory,
+    /// instead of loading a config. Can be repeated.
+    #[arg(short, long, value_name = "PATTERN", conflicts_with = "config")]
+    pub watch: Vec<String>,
+    /// Leave out paths matching this pattern when watching without a
+    /// config. Can be repeated.
+    #[arg(short, long, value_name = "PATTERN", conflicts_with = "config")]
+    pub ignore: Vec<String>,
+    /// Clear the terminal before each run when watching without a config.
+    #[arg(long, conflicts_with = "config")]
+    pub clear_screen: bool,
+    /// Run the command once on start when watching without a config.
+    #[arg(long, conflicts_with = "config")]
+    pub run_on_start: bool,
+    /// Ring the bell after each run when watching without a config.
+    #[arg(long, conflicts_with = "config")]
+    pub bell: bool,
+    /// The command to run on changes when watching without a config.
+    #[arg(last = true, value_name = "COMMAND")]
+    pub exec: Vec<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// The config to run, loaded from `--config` or made up from the
+    /// command after `--`.
+    pub fn load_config(&self) -> Result<Config, ()> {
+        let mut config = match self.exec.is_empty() {
+            true if !self.watch.is_empty() || !self.ignore.is_empty() => {
+                error!("Missing the Command to Run after --");
+                return Err(());
+            }
+            true => Config::load(&self.config)?,
+            false => {
+                let config = Config {
+                    nodes: vec![Box::from(self.ad_hoc_node())],
+                    ..Default::default()
+                };
+                config.validate()?;
+                config
+            }
+        };
+        config.socket = self.socket.clone().or(config.socket);
+        config.state_file = self.state_file.clone().or(config.state_file);
+        config.catch_up |= self.catch_up;
+        return Ok(config);
+    }
+
+    /// A node running the command after `--` on changes to the `--watch`
+    /// patterns, or to anything below the current directory without them.
+    fn ad_hoc_node(&self) -> Node {
+        let path_pattern = match self.watch.as_slice() {
+            [] => String::from("**/*"),
+            [pattern] => pattern.clone(),
+            patterns => format!("{{{}}}", patterns.join(",")),
+        };
+        // A single argument is taken as a shell command, so `-- 'make && make
+        // test'` works, otherwise the arguments are passed on as they are.
+        let exec = match self.exec.as_slice() {
+            [command] => command.clone(),
+            args => args
+                .iter()
+                .map(|arg| quote(arg))
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        return Node {
+            root: PathBuf::from("."),
+            path_pattern: Some(path_pattern),
+            ignore: self.ignore.clone(),
+            exec: Some(exec),
+            clear_screen: self.clear_screen,
+            run_on_start: self.run_on_start,
+            bell: self.bell,
+            ..Default::default()
+        };
+    }
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Watch the config's nodes and run them on changes, the default.
+    Watch,
+    /// Run the named nodes and their dependents once, then exit.
+    Run {
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// Print the tree of watched paths and the nodes owning them, then exit.
+    Tree,
+    /// Print why each node does or does not match a path and what it would
+    /// run on a change to it, then exit.
+    Explain { path: PathBuf },
+    /// Print the files changed since the snapshot in the state file, then
+    /// save a new one. Exits with 1 if any node's files changed.
+    Changed {
+        /// Compare against this Unix timestamp, in seconds, instead of the
+        /// snapshot.
+        #[arg(long, value_name = "SECONDS")]
+        since: Option<u64>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_cli_parse() {
+        let cli =
+            Cli::try_parse_from(["irminsul", "--only", "api,web", "--except", "docs"]).unwrap();
+        assert_eq!(cli.config, PathBuf::from("irminsul.json"));
+        assert_eq!(cli.only, vec!["api".to_owned(), "web".to_owned()]);
+        assert_eq!(cli.except, vec!["docs".to_owned()]);
+        assert_eq!(cli.command, None);
+        assert!(!cli.json);
+
+        let cli = Cli::try_parse_from(["irminsul", "run", "api", "web", "-vv", "-c", "dev.json"])
+            .unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::Run {
+                names: vec!["api".to_owned(), "web".to_owned()]
+            })
+        );
+        assert_eq!(cli.verbose, 2);
+        assert_eq!(cli.config, PathBuf::from("dev.json"));
+        assert!(Cli::try_parse_from(["irminsul", "run"]).is_err());
+        assert_eq!(
+            Cli::try_parse_from(["irminsul", "tree", "--only", "api"])
+                .unwrap()
+                .command,
+            Some(Command::Tree)
+        );
+        assert_eq!(
+            Cli::try_parse_from(["irminsul", "explain", "src/main.rs"])
+                .unwrap()
+                .command,
+            Some(Command::Explain {
+                path: PathBuf::from("src/main.rs")
+            })
+        );
+        let cli = Cli::try_parse_from(["irminsul", "changed", "--since", "1700000000"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::Changed {
+                since: Some(1700000000)
+            })
+        );
+        assert!(
+            Cli::try_parse_from(["irminsul", "watch", "--json"])
+                .unwrap()
+                .json
+        );
+    }
+
+    #[test]
+    fn test_cli_ad_hoc() {
+        let cli = Cli::try_parse_from([
+            "irminsul",
+            "-w",
+            "src/**/*.rs",
+            "-w",
+            "Cargo.toml",
+            "-i",
+            "target",
+            "--",
+            "cargo",
+            "test",
+            "--features",
+            "notify timestamps",
+        ])
+        .unwrap();
+        assert_eq!(cli.command, None);
+        let config = cli.load_config().unwrap();
+        assert_eq!(
+            *config.nodes[0],
+            Node {
+                root: PathBuf::from("."),
+                path_pattern: Some(String::from("{src/**/*.rs,Cargo.toml}")),
+                ignore: vec![String::from("target")],
+                exec: Some(String::from("cargo test --features 'notify timestamps'")),
+                ..Default::default()
+            }
+        );
+        assert!(config.nodes[0].matches(&config.nodes[0].normalized_root().join("Cargo.toml")));
+
+        let cli =
+            Cli::try_parse_from(["irminsul", "--run-on-start", "--", "make && make test"]).unwrap();
+        let conf_node = cli.ad_hoc_node();
+        assert!(conf_node.run_on_start);
+        assert_eq!(conf_node.path_pattern, Some(String::from("**/*")));
+        assert_eq!(conf_node.exec, Some(String::from("make && make test")));
+
+        let cli = Cli::try_parse_from(["irminsul", "-w", "*.rs"]).unwrap();
+        assert!(cli.load_config().is_err());
+        assert!(Cli::try_parse_from(["irminsul", "-c", "dev.json", "-w", "*.rs"]).is_err());
+    }
+}
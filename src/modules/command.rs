@@ -0,0 +1,813 @@
+use log::{error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    env::temp_dir,
+    ffi::OsString,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use subprocess::{ExitStatus, Popen, PopenConfig, Redirection};
+
+use super::{
+    events::{self, Event},
+    node::Node,
+    output::{self, Stream},
+    signals,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Self::Created => "created",
+            Self::Modified => "modified",
+            Self::Removed => "removed",
+        };
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct Change {
+    pub path: PathBuf,
+    pub kind: EventKind,
+}
+
+/// Quotes `arg` so the platform shell passes it through as a single word.
+pub fn quote(arg: &str) -> String {
+    if cfg!(windows) {
+        if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"&|<>^%()".contains(c))
+        {
+            return arg.to_owned();
+        }
+        return format!("\"{}\"", arg.replace('"', "\"\""));
+    }
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./,:=+@%".contains(c))
+    {
+        return arg.to_owned();
+    }
+    return format!("'{}'", arg.replace('\'', r"'\''"));
+}
+
+fn quote_path(path: &Path) -> String {
+    quote(&path.to_string_lossy())
+}
+
+/// Splits `command` into words the way a POSIX shell would, without any
+/// expansion. Undoes [`quote`].
+pub fn split(command: &str) -> Result<Vec<String>, ()> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => {
+                            error!("Unclosed ' in {command}");
+                            return Err(());
+                        }
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => word.push('\\'),
+                        },
+                        Some(c) => word.push(c),
+                        None => {
+                            error!("Unclosed \" in {command}");
+                            return Err(());
+                        }
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            }
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    return Ok(words);
+}
+
+/// How a node's command is started.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    /// `sh -c`, or `cmd /C` on Windows.
+    #[default]
+    Default,
+    /// Split into words and run directly, without a shell.
+    None,
+    /// This program and its arguments, followed by the command, eg.
+    /// `["bash", "-lc"]`.
+    Custom(Vec<String>),
+}
+
+impl Shell {
+    /// The program running `command` followed by its arguments.
+    pub fn argv(&self, command: &str) -> Result<Vec<String>, ()> {
+        return match self {
+            Self::Default if cfg!(windows) => {
+                Ok(vec!["cmd.exe".into(), "/c".into(), command.into()])
+            }
+            Self::Default => Ok(vec!["sh".into(), "-c".into(), command.into()]),
+            Self::None => match split(command)? {
+                argv if argv.is_empty() => {
+                    error!("Command is Empty");
+                    Err(())
+                }
+                argv => Ok(argv),
+            },
+            Self::Custom(shell) if shell.is_empty() => {
+                error!("Shell is Empty");
+                Err(())
+            }
+            Self::Custom(shell) => Ok(shell.iter().cloned().chain([command.into()]).collect()),
+        };
+    }
+}
+
+/// The value of a single placeholder. Per-change placeholders refer to the
+/// first change of the batch, `{paths}` to all of them.
+fn placeholder(name: &str, conf_node: &Node, changes: &[Change]) -> Option<String> {
+    let root = conf_node.normalized_root();
+    let first = changes.first();
+    let path = first.map(|change| change.path.as_path());
+    let value = match name {
+        "path" => quote_path(path.unwrap_or(Path::new(""))),
+        "paths" => changes
+            .iter()
+            .map(|change| quote_path(&change.path))
+            .collect::<Vec<_>>()
+            .join(" "),
+        "relpath" => quote_path(
+            path.map(|path| path.strip_prefix(&root).unwrap_or(path))
+                .unwrap_or(Path::new("")),
+        ),
+        "dir" => quote_path(path.and_then(Path::parent).unwrap_or(Path::new(""))),
+        "stem" => quote(
+            &path
+                .and_then(Path::file_stem)
+                .map(|stem| stem.to_string_lossy())
+                .unwrap_or_default(),
+        ),
+        "ext" => quote(
+            &path
+                .and_then(Path::extension)
+                .map(|ext| ext.to_string_lossy())
+                .unwrap_or_default(),
+        ),
+        "event" => quote(first.map(|change| change.kind.as_str()).unwrap_or("")),
+        "node_root" => quote_path(&root),
+        _ => return None,
+    };
+    return Some(value);
+}
+
+/// Expands the placeholders in `template` for a batch of changes. Anything in
+/// braces that is not a known placeholder, like `${HOME}`, is left as is.
+pub fn expand(template: &str, conf_node: &Node, changes: &[Change]) -> String {
+    let mut command = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        command.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest
+            .find('}')
+            .and_then(|end| Some((end, placeholder(&rest[1..end], conf_node, changes)?)));
+        match value {
+            Some((end, value)) => {
+                command.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                command.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    command.push_str(rest);
+    return command;
+}
+
+/// Env vars longer than this are left out, scripts can read the batch file
+/// instead. Linux refuses single strings over 128KiB.
+const MAX_ENV_LEN: usize = 64 * 1024;
+
+static BATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The full batch, written to a temp file for the command to read.
+#[derive(Serialize, Debug)]
+struct BatchFile<'a> {
+    node: &'a Node,
+    changes: &'a [Change],
+}
+
+fn write_batch_file(conf_node: &Node, changes: &[Change]) -> Result<PathBuf, ()> {
+    let path = temp_dir().join(format!(
+        "irminsul-{}-{}.json",
+        process::id(),
+        BATCH_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let json = serde_json::to_vec(&BatchFile {
+        node: conf_node,
+        changes,
+    })
+    .map_err(|err| error!("Failed to Serialize Batch: {err}"))?;
+    // The name is predictable, so never write through whatever is already
+    // there.
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&json))
+        .map_err(|err| error!("Failed to Write Batch File {}: {err}", path.display()))?;
+    return Ok(path);
+}
+
+/// The variables describing a batch of changes, one path or kind per line.
+pub fn batch_env(conf_node: &Node, changes: &[Change]) -> Vec<(&'static str, OsString)> {
+    let mut paths = OsString::new();
+    let mut kinds = String::new();
+    for (index, change) in changes.iter().enumerate() {
+        if index > 0 {
+            paths.push("\n");
+            kinds.push('\n');
+        }
+        paths.push(&change.path);
+        kinds.push_str(change.kind.as_str());
+    }
+    if paths.len() > MAX_ENV_LEN {
+        warn!(
+            "Leaving out IRMINSUL_CHANGED_PATHS for {} Changes, Read IRMINSUL_BATCH_FILE Instead",
+            changes.len()
+        );
+        paths.clear();
+    }
+    return vec![
+        ("IRMINSUL_CHANGED_PATHS", paths),
+        ("IRMINSUL_EVENT_KINDS", kinds.into()),
+        ("IRMINSUL_NODE", conf_node.label().into()),
+    ];
+}
+
+/// How long a stopped job gets to exit before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the rest of an exited job's output.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often a waiting job checks whether it was cancelled.
+const CANCEL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Tells jobs waited on with it to stop, from any thread. Clones share the
+/// same state.
+#[derive(Clone, Debug, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether this was cancelled or irminsul is shutting down.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst) || signals::received().is_some()
+    }
+
+    /// Sleeps for `duration` unless cancelled first, returning whether it slept
+    /// the whole time.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.is_cancelled() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
+            }
+            thread::sleep(remaining.min(CANCEL_INTERVAL));
+        }
+        return false;
+    }
+}
+
+/// A started command along with the batch file it was handed.
+#[derive(Debug)]
+pub struct Job {
+    label: String,
+    command: String,
+    process: Popen,
+    /// The process group the job leads, kept after the job itself exits to
+    /// reach what it started.
+    #[cfg(unix)]
+    group: Option<u32>,
+    /// Removed once the job is seen to exit.
+    batch_file: Option<PathBuf>,
+    started: Instant,
+    /// Signalled by each thread forwarding the job's output once it is done.
+    forwarded: Receiver<()>,
+}
+
+impl Job {
+    /// Starts `command` for `conf_node` with the batch described in its env.
+    pub fn start(command: &str, conf_node: &Node, changes: &[Change]) -> Result<Self, ()> {
+        info!("Running {command}");
+        let batch_file = write_batch_file(conf_node, changes)?;
+        let mut process = conf_node
+            .process(command)
+            .and_then(|(argv, mut config)| {
+                let env = config.env.get_or_insert_with(PopenConfig::current_env);
+                env.extend(
+                    batch_env(conf_node, changes)
+                        .into_iter()
+                        .map(|(key, value)| (key.into(), value)),
+                );
+                env.push(("IRMINSUL_BATCH_FILE".into(), batch_file.clone().into()));
+                // The terminal's keys are for irminsul, and commands get a
+                // process group of their own so stopping one stops everything
+                // it started.
+                config.stdin = Redirection::Pipe;
+                config.stdout = Redirection::Pipe;
+                config.stderr = Redirection::Pipe;
+                #[cfg(unix)]
+                {
+                    config.setpgid = true;
+                }
+                Popen::create(&argv, config).map_err(|err| error!("Failed to Run {command}: {err}"))
+            })
+            .map_err(|_| remove_batch_file(&batch_file))?;
+        // Closing stdin right away makes reading it hit the end.
+        drop(process.stdin.take());
+        let label = conf_node.label();
+        let (done, forwarded) = mpsc::channel();
+        let streams = [
+            (
+                Stream::Stdout,
+                process
+                    .stdout
+                    .take()
+                    .map(|out| Box::new(out) as Box<dyn Read + Send>),
+            ),
+            (
+                Stream::Stderr,
+                process
+                    .stderr
+                    .take()
+                    .map(|err| Box::new(err) as Box<dyn Read + Send>),
+            ),
+        ];
+        for (stream, reader) in streams {
+            let (label, done) = (label.clone(), done.clone());
+            let Some(reader) = reader else {
+                let _ = done.send(());
+                continue;
+            };
+            thread::spawn(move || {
+                output::forward(&label, stream, reader);
+                let _ = done.send(());
+            });
+        }
+        events::emit(&Event::Started {
+            node: label.clone(),
+            command: command.to_owned(),
+            pid: process.pid(),
+        });
+        return Ok(Self {
+            label,
+            command: command.to_owned(),
+            #[cfg(unix)]
+            group: process.pid(),
+            process,
+            batch_file: Some(batch_file),
+            started: Instant::now(),
+            forwarded,
+        });
+    }
+
+    /// Waits for the job to exit, stopping it once `timeout` runs out or
+    /// `cancel` is cancelled, and logs how it went. Fails if it was stopped.
+    pub fn wait(mut self, timeout: Option<Duration>, cancel: &Cancel) -> Result<ExitStatus, ()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let status = loop {
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let status = self
+                .process
+                .wait_timeout(remaining.unwrap_or(CANCEL_INTERVAL).min(CANCEL_INTERVAL))
+                .map_err(|err| error!("Failed to Wait for {}: {err}", self.command))?;
+            if let Some(status) = status {
+                self.remove_batch_file();
+                break Ok(status);
+            }
+            if cancel.is_cancelled() {
+                break Err("cancelled");
+            }
+            if remaining.is_some_and(|remaining| remaining.is_zero()) {
+                break Err("timed_out");
+            }
+        };
+        let duration = self.started.elapsed().as_millis();
+        let status = match status {
+            Ok(status) => status,
+            Err(reason) => {
+                error!(
+                    "node={:?} command={:?} status={reason} duration_ms={duration}",
+                    self.label, self.command
+                );
+                self.stop_because(reason);
+                return Err(());
+            }
+        };
+        self.flush_output();
+        info!(
+            "node={:?} command={:?} status={} duration_ms={duration}",
+            self.label,
+            self.command,
+            describe(&status)
+        );
+        events::emit(&Event::Exited {
+            node: self.label.clone(),
+            command: self.command.clone(),
+            status: describe(&status),
+            duration_ms: duration,
+        });
+        return Ok(status);
+    }
+
+    /// Gives the output of an exited job a moment to be printed, in case
+    /// something it started in the background still holds on to its pipes.
+    fn flush_output(&self) {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        for _ in 0..2 {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if self.forwarded.recv_timeout(timeout).is_err() {
+                return;
+            }
+        }
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        let running = self.process.poll().is_none();
+        if !running {
+            self.remove_batch_file();
+        }
+        return running;
+    }
+
+    fn remove_batch_file(&mut self) {
+        if let Some(batch_file) = self.batch_file.take() {
+            remove_batch_file(&batch_file);
+        }
+    }
+
+    /// Sends `signal` to every process in the job's process group.
+    #[cfg(unix)]
+    fn signal(&self, signal: i32) -> io::Result<()> {
+        let Some(group) = self.group else {
+            return Ok(());
+        };
+        // SAFETY: kill only reads its arguments, a negative pid addresses a
+        // process group.
+        return match unsafe { libc::kill(-(group as libc::pid_t), signal) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        };
+    }
+
+    /// Asks the job to exit, passing on the signal irminsul is stopping on.
+    fn terminate(&mut self) -> io::Result<()> {
+        #[cfg(unix)]
+        return self.signal(signals::received().unwrap_or(libc::SIGTERM));
+        #[cfg(not(unix))]
+        return self.process.terminate();
+    }
+
+    fn kill(&mut self) -> io::Result<()> {
+        #[cfg(unix)]
+        return self.signal(libc::SIGKILL);
+        #[cfg(not(unix))]
+        return self.process.kill();
+    }
+
+    /// Whether anything the job started is still up in its process group,
+    /// even after the job itself exited.
+    fn has_members(&self) -> bool {
+        #[cfg(unix)]
+        return self.signal(0).is_ok();
+        #[cfg(not(unix))]
+        return false;
+    }
+
+    /// Whether the job exited by `deadline`, along with everything it
+    /// started.
+    fn has_exited(&mut self, deadline: Instant) -> bool {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        if !matches!(self.process.wait_timeout(timeout), Ok(Some(_))) {
+            return false;
+        }
+        #[cfg(unix)]
+        while self.signal(0).is_ok() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(CANCEL_INTERVAL);
+        }
+        return true;
+    }
+
+    /// Asks the job to exit, killing it along with anything it started if it
+    /// has not after [`STOP_TIMEOUT`].
+    pub fn stop(&mut self) {
+        self.stop_because("stopped");
+    }
+
+    fn stop_because(&mut self, reason: &str) {
+        if self.is_running() {
+            info!("Stopping {}", self.command);
+            events::emit(&Event::Killed {
+                node: self.label.clone(),
+                command: self.command.clone(),
+                reason: reason.to_owned(),
+                duration_ms: self.started.elapsed().as_millis(),
+            });
+        } else if self.has_members() {
+            info!("Stopping What {} Left Running", self.command);
+        } else {
+            return;
+        }
+        let _ = self
+            .terminate()
+            .map_err(|err| error!("Failed to Stop {}: {err}", self.command));
+        if self.has_exited(Instant::now() + STOP_TIMEOUT) {
+            return;
+        }
+        warn!("Killing {} after {STOP_TIMEOUT:?}", self.command);
+        let _ = self
+            .kill()
+            .map_err(|err| error!("Failed to Kill {}: {err}", self.command));
+        let _ = self.process.wait();
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.stop();
+        self.remove_batch_file();
+    }
+}
+
+fn remove_batch_file(batch_file: &Path) {
+    let _ = fs::remove_file(batch_file).map_err(|err| {
+        trace!(
+            "Failed to Remove Batch File {}: {err}",
+            batch_file.display()
+        )
+    });
+}
+
+/// The exit status as logged, eg. `0` or `signal_9`.
+pub fn describe(status: &ExitStatus) -> String {
+    match status {
+        ExitStatus::Exited(code) => code.to_string(),
+        ExitStatus::Signaled(signal) => format!("signal_{signal}"),
+        ExitStatus::Other(code) => format!("other_{code}"),
+        ExitStatus::Undetermined => String::from("undetermined"),
+    }
+}
+
+/// Runs `command` to completion, see [`Job::start`].
+pub fn run(command: &str, conf_node: &Node, changes: &[Change]) -> Result<(), ()> {
+    let status = Job::start(command, conf_node, changes)?.wait(None, &Cancel::default())?;
+    return match conf_node.is_success(&status) {
+        true => Ok(()),
+        false => Err(()),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn conf_node() -> Node {
+        Node {
+            root: PathBuf::from("/home/bob_ross"),
+            path_pattern: Some(String::from("**/*.png")),
+            exec: None,
+            ..Default::default()
+        }
+    }
+
+    fn changes() -> Vec<Change> {
+        vec![
+            Change {
+                path: PathBuf::from("/home/bob_ross/WIP/the old mill.png"),
+                kind: EventKind::Modified,
+            },
+            Change {
+                path: PathBuf::from("/home/bob_ross/mountain_retreat.png"),
+                kind: EventKind::Created,
+            },
+        ]
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_command_expand() {
+        let conf_node = conf_node();
+        let changes = changes();
+        assert_eq!(
+            expand("convert {path} {dir}/{stem}.jpg", &conf_node, &changes),
+            "convert '/home/bob_ross/WIP/the old mill.png' /home/bob_ross/WIP/'the old mill'.jpg"
+        );
+        assert_eq!(
+            expand(
+                "echo {event} {relpath} {ext} {node_root}",
+                &conf_node,
+                &changes
+            ),
+            "echo modified 'WIP/the old mill.png' png /home/bob_ross"
+        );
+        assert_eq!(
+            expand("optipng {paths}", &conf_node, &changes),
+            "optipng '/home/bob_ross/WIP/the old mill.png' /home/bob_ross/mountain_retreat.png"
+        );
+        assert_eq!(
+            expand("echo ${HOME} {unknown} {path", &conf_node, &changes[1..]),
+            "echo ${HOME} {unknown} {path"
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_command_quote() {
+        assert_eq!(quote("plain/path.txt"), "plain/path.txt");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("bob's mill"), r"'bob'\''s mill'");
+        assert_eq!(quote("$(rm -rf ~)"), "'$(rm -rf ~)'");
+    }
+
+    #[test]
+    fn test_command_per_file() {
+        let conf_node = Node {
+            exec: Some(String::from("rustfmt {path}")),
+            per_file: true,
+            ..conf_node()
+        };
+        let changes = changes();
+        assert_eq!(
+            conf_node.commands(&changes),
+            changes
+                .iter()
+                .map(|change| format!("rustfmt {}", quote_path(&change.path)))
+                .collect::<Vec<_>>()
+        );
+        let conf_node = Node {
+            per_file: false,
+            ..conf_node
+        };
+        assert_eq!(conf_node.commands(&changes).len(), 1);
+        assert_eq!(
+            Node {
+                exec: None,
+                ..conf_node
+            }
+            .commands(&changes),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_command_run_env() {
+        let out = temp_dir().join("command_run_env_test.txt");
+        let conf_node = conf_node();
+        let changes = changes();
+        let command = format!(
+            "{{ printf '%s|%s|%s\\n' \"$IRMINSUL_CHANGED_PATHS\" \"$IRMINSUL_EVENT_KINDS\" \"$IRMINSUL_NODE\"; cat \"$IRMINSUL_BATCH_FILE\"; }} > {}",
+            quote_path(&out)
+        );
+        assert_eq!(run(&command, &conf_node, &changes), Ok(()));
+
+        let output = fs::read_to_string(&out).unwrap();
+        assert_eq!(
+            output.lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "/home/bob_ross/WIP/the old mill.png",
+                "/home/bob_ross/mountain_retreat.png|modified",
+                "created|/home/bob_ross",
+            ]
+        );
+        let batch: serde_json::Value =
+            serde_json::from_str(output.lines().skip(3).collect::<String>().as_str()).unwrap();
+        assert_eq!(batch["changes"], serde_json::to_value(&changes).unwrap());
+        assert_eq!(batch["node"]["root"], "/home/bob_ross");
+        assert_eq!(run("false", &conf_node, &changes), Err(()));
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_command_stop_left_running() {
+        let out = temp_dir().join("command_left_running_test.txt");
+        let _ = fs::remove_file(&out);
+        // The leftover does not hold on to the job's output, so the job is
+        // done as soon as the shell exits.
+        let command = format!(
+            "(sleep 1; echo late > {}) > /dev/null 2>&1 &",
+            quote_path(&out)
+        );
+        let job = Job::start(&command, &conf_node(), &changes()).unwrap();
+        assert!(job.wait(None, &Cancel::default()).is_ok());
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!out.exists());
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_command_remove_batch_file() {
+        let mut job = Job::start("true", &conf_node(), &changes()).unwrap();
+        let batch_file = job.batch_file.clone().unwrap();
+        assert!(batch_file.exists());
+        while job.is_running() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!batch_file.exists());
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_command_stop_process_group() {
+        let out = temp_dir().join("command_stop_test.txt");
+        let _ = fs::remove_file(&out);
+        let command = format!("(sleep 1; echo late > {}) & wait", quote_path(&out));
+        let mut job = Job::start(&command, &conf_node(), &changes()).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        job.stop();
+        assert!(start.elapsed() < STOP_TIMEOUT);
+        assert!(!job.is_running());
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn test_command_split() {
+        assert_eq!(
+            split(r#"convert 'the old mill.png' "bob \"ross\"" a\ b  'it'\''s'"#),
+            Ok(vec![
+                "convert".to_owned(),
+                "the old mill.png".to_owned(),
+                r#"bob "ross""#.to_owned(),
+                "a b".to_owned(),
+                "it's".to_owned(),
+            ])
+        );
+        assert_eq!(split("echo ''"), Ok(vec!["echo".to_owned(), String::new()]));
+        assert_eq!(split("echo 'unclosed"), Err(()));
+    }
+}
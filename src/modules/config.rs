@@ -1,8 +1,20 @@
 #[cfg(all(feature = "notify", target_family = "windows"))]
 use super::modes::{mix::Mix, notify::Notify};
-use super::{modes::poll::Poll, node::Node};
+use super::{
+    graph::Graph,
+    modes::{poll::Poll, Poll as _},
+    node::Node,
+    treemap::{PointId, Treemap},
+};
 
+use log::error;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum Mode {
@@ -14,6 +26,47 @@ pub enum Mode {
     Mix(Mix),
 }
 
+impl Mode {
+    pub fn poll_rate(&self) -> Duration {
+        return match self {
+            #[cfg(feature = "poll")]
+            Self::Poll(mode) => mode.poll_rate,
+            #[cfg(all(feature = "notify", target_family = "windows"))]
+            Self::Notify(mode) => mode.poll_rate,
+            #[cfg(all(feature = "poll", feature = "notify", target_family = "windows"))]
+            Self::Mix(mode) => mode.poll_rate,
+        };
+    }
+
+    /// The points a poll starts from, for modes that pick some instead of
+    /// stat-ing every leaf.
+    #[cfg_attr(
+        not(all(feature = "poll", feature = "notify", target_family = "windows")),
+        allow(unused_variables)
+    )]
+    pub fn polled_points(&self, files: &Treemap) -> BTreeSet<PointId> {
+        return match self {
+            #[cfg(feature = "poll")]
+            Self::Poll(_) => BTreeSet::new(),
+            #[cfg(all(feature = "notify", target_family = "windows"))]
+            Self::Notify(_) => BTreeSet::new(),
+            #[cfg(all(feature = "poll", feature = "notify", target_family = "windows"))]
+            Self::Mix(mode) => mode.polled_points(files).into_iter().collect(),
+        };
+    }
+
+    pub fn poll(&self, files: &mut Treemap) -> Option<Vec<PathBuf>> {
+        return match self {
+            #[cfg(feature = "poll")]
+            Self::Poll(mode) => mode.poll(files),
+            #[cfg(all(feature = "notify", target_family = "windows"))]
+            Self::Notify(mode) => mode.poll(files),
+            #[cfg(all(feature = "poll", feature = "notify", target_family = "windows"))]
+            Self::Mix(mode) => mode.poll(files),
+        };
+    }
+}
+
 impl Default for Mode {
     #[cfg(all(feature = "notify", feature = "poll", target_family = "windows"))]
     fn default() -> Self {
@@ -27,8 +80,7 @@ impl Default for Mode {
 
     #[cfg(all(
         feature = "poll",
-        not(feature = "notify"),
-        not(target_family = "windows")
+        not(all(feature = "notify", target_family = "windows"))
     ))]
     fn default() -> Self {
         return Self::Poll(Poll::default());
@@ -36,9 +88,236 @@ impl Default for Mode {
 }
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
-struct Config {
-    verbosity: usize,
-    mode: Mode,
-    #[serde(flatten)]
-    nodes: Vec<Box<Node>>,
+#[serde(default)]
+pub struct Config {
+    pub verbosity: usize,
+    pub mode: Mode,
+    /// How many nodes may run at once, one per CPU when 0.
+    pub max_parallel: usize,
+    /// Unix socket to take requests on while watching, none by default.
+    pub socket: Option<PathBuf>,
+    /// File the watched files and their modification times are saved to on
+    /// exit, to find what changed until the next start.
+    pub state_file: Option<PathBuf>,
+    /// Run the nodes whose files changed while irminsul was not running,
+    /// instead of only logging the changes.
+    pub catch_up: bool,
+    pub nodes: Vec<Box<Node>>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ()> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| error!("Failed to Read Config {}: {err}", path.display()))?;
+        let config: Self = serde_json::from_str(&contents)
+            .map_err(|err| error!("Failed to Parse Config {}: {err}", path.display()))?;
+        config.validate()?;
+        return Ok(config);
+    }
+
+    /// The index of the node called `name`.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|conf_node| conf_node.name.as_deref() == Some(name))
+    }
+
+    /// Keeps the nodes named in `only`, if any, minus those in `except`.
+    /// Dependencies on nodes left out are dropped along with them.
+    pub fn select(&mut self, only: &[String], except: &[String]) -> Result<(), ()> {
+        let unknown = only
+            .iter()
+            .chain(except)
+            .filter(|name| self.position(name).is_none())
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            unknown
+                .iter()
+                .for_each(|name| error!("No Node is Named {name}"));
+            return Err(());
+        }
+        self.nodes.retain(|conf_node| {
+            let name = conf_node.name.as_ref();
+            (only.is_empty() || name.is_some_and(|name| only.contains(name)))
+                && !name.is_some_and(|name| except.contains(name))
+        });
+        let names = self
+            .nodes
+            .iter()
+            .filter_map(|conf_node| conf_node.name.clone())
+            .collect::<Vec<_>>();
+        for conf_node in self.nodes.iter_mut() {
+            conf_node.depends_on.retain(|name| names.contains(name));
+        }
+        return Ok(());
+    }
+
+    /// Validates every node and the dependencies between them, so all
+    /// problems are reported at once.
+    pub fn validate(&self) -> Result<(), ()> {
+        let mut results = self
+            .nodes
+            .iter()
+            .map(|conf_node| conf_node.validate())
+            .collect::<Vec<_>>();
+        results.push(Graph::new(&self.nodes).map(|_| ()));
+        return results.into_iter().collect();
+    }
+
+    /// One tree shared by every node, so paths watched by several of them
+    /// are only stat'd once.
+    pub fn build_treemap(&self) -> Treemap {
+        return Treemap::from_nodes(&self.nodes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+    use std::{env::temp_dir, path::PathBuf};
+
+    use super::*;
+    use crate::modules::{command::Shell, node::OnFailure};
+
+    #[test]
+    #[serial]
+    fn test_config_load() {
+        let dir = temp_dir().join("config_load_test1");
+        let _ = fs::create_dir_all(dir.join("server"));
+        fs::write(dir.join(".env"), "PORT=8080\n").unwrap();
+        let config_path = dir.join("irminsul.json");
+        fs::write(
+            &config_path,
+            serde_json::json!({
+                "nodes": [{
+                    "root": dir,
+                    "path_pattern": "**/*.rs",
+                    "exec": "cargo run",
+                    "cwd": "server",
+                    "env_file": ".env",
+                    "env": {"ADDR": "localhost:${PORT}"},
+                    "shell": {"custom": ["bash", "-lc"]},
+                    "clear_env": true,
+                    "timeout": 60,
+                    "success_codes": [0, 1],
+                    "on_failure": {"retry": {"attempts": 3, "backoff": 500}},
+                }]
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        let conf_node = &config.nodes[0];
+        assert_eq!(
+            conf_node.shell,
+            Shell::Custom(vec!["bash".to_owned(), "-lc".to_owned()])
+        );
+        assert_eq!(conf_node.working_dir(), Some(dir.join("server")));
+        assert_eq!(
+            conf_node.on_failure,
+            OnFailure::Retry {
+                attempts: 3,
+                backoff: 500
+            }
+        );
+        assert_eq!(
+            conf_node.environment(),
+            Ok(vec![
+                ("PORT".to_owned(), "8080".to_owned()),
+                ("ADDR".to_owned(), "localhost:8080".to_owned()),
+            ])
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_config_load_invalid() {
+        let dir = temp_dir().join("config_load_test2");
+        let _ = fs::create_dir_all(&dir);
+        let config_path = dir.join("irminsul.json");
+        let write = |node: serde_json::Value| {
+            fs::write(
+                &config_path,
+                serde_json::json!({ "nodes": [node] }).to_string(),
+            )
+            .unwrap();
+        };
+
+        write(serde_json::json!({"root": dir, "cwd": "missing"}));
+        assert_eq!(Config::load(&config_path).map(|_| ()), Err(()));
+        write(serde_json::json!({"root": dir, "env": {"A": "${IRMINSUL_UNDEFINED}"}}));
+        assert_eq!(Config::load(&config_path).map(|_| ()), Err(()));
+        write(serde_json::json!({"root": dir, "env_file": "missing.env"}));
+        assert_eq!(Config::load(&config_path).map(|_| ()), Err(()));
+        write(serde_json::json!({"root": dir, "exec": "echo 'unclosed", "shell": "none"}));
+        assert_eq!(Config::load(&config_path).map(|_| ()), Err(()));
+        write(serde_json::json!({"root": dir, "exec": "echo", "shell": {"custom": []}}));
+        assert_eq!(Config::load(&config_path).map(|_| ()), Err(()));
+        write(serde_json::json!({"root": dir, "exec": "echo", "steps": [{"exec": "echo"}]}));
+        assert_eq!(Config::load(&config_path).map(|_| ()), Err(()));
+        write(serde_json::json!({"root": dir, "steps": [
+            {"exec": "cargo run", "long_running": true},
+            {"exec": "cargo test"},
+        ]}));
+        assert_eq!(Config::load(&config_path).map(|_| ()), Err(()));
+        write(serde_json::json!({"name": "a", "root": dir, "depends_on": ["a"]}));
+        assert_eq!(Config::load(&config_path).map(|_| ()), Err(()));
+        write(serde_json::json!({"root": dir, "exec": "echo 'ok'", "shell": "none"}));
+        assert_eq!(
+            Config::load(&config_path).map(|config| config.nodes[0].root.clone()),
+            Ok(PathBuf::from(&dir))
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_config_select() {
+        let conf_node = |name: Option<&str>, depends_on: &[&str]| {
+            Box::from(Node {
+                name: name.map(str::to_owned),
+                depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+                ..Default::default()
+            })
+        };
+        let config = Config {
+            nodes: vec![
+                conf_node(Some("api"), &["proto"]),
+                conf_node(Some("web"), &["api"]),
+                conf_node(Some("proto"), &[]),
+                conf_node(Some("docs"), &[]),
+                conf_node(None, &[]),
+            ],
+            ..Default::default()
+        };
+        let names = |config: &Config| {
+            config
+                .nodes
+                .iter()
+                .map(|conf_node| conf_node.label())
+                .collect::<Vec<_>>()
+        };
+        let strings = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut only = config.clone();
+        assert_eq!(only.select(&strings(&["api", "web"]), &[]), Ok(()));
+        assert_eq!(names(&only), vec!["api", "web"]);
+        assert_eq!(only.nodes[0].depends_on, Vec::<String>::new());
+        assert_eq!(only.nodes[1].depends_on, vec!["api"]);
+
+        let mut except = config.clone();
+        assert_eq!(except.select(&[], &strings(&["docs", "proto"])), Ok(()));
+        assert_eq!(names(&except), vec!["api", "web", ""]);
+        assert_eq!(except.position("web"), Some(1));
+
+        let mut unknown = config;
+        assert_eq!(unknown.select(&strings(&["missing"]), &[]), Err(()));
+    }
 }
@@ -0,0 +1,451 @@
+use log::{error, info, trace};
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use std::{
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    sync::mpsc::RecvTimeoutError,
+    thread,
+    time::Duration,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use super::{config::Config, events, scheduler::Scheduler, treemap::Treemap};
+
+/// What a client of the control socket asks for, one JSON object per line
+/// keyed by `command`.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// The nodes and what they are up to.
+    List,
+    /// Runs the node called `node`.
+    Trigger {
+        node: String,
+    },
+    Pause,
+    Resume,
+    /// Every path in the tree and the nodes owning it.
+    Tree,
+    /// Streams events as `--json` prints them until the client disconnects.
+    Subscribe,
+}
+
+/// The answer to a [`Request`], keyed by `response`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Nodes {
+        nodes: Vec<NodeStatus>,
+    },
+    Tree {
+        paths: Vec<TreePath>,
+    },
+    /// Events follow, one per line.
+    Subscribed,
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeState {
+    Idle,
+    Queued,
+    Running,
+    /// Stopped watching after a failure.
+    Disabled,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeStatus {
+    pub node: String,
+    pub state: NodeState,
+    /// Whether the last run succeeded, if the node ran yet.
+    pub succeeded: Option<bool>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct TreePath {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub nodes: Vec<String>,
+}
+
+/// The state of every node in `config`.
+pub fn list(config: &Config, scheduler: &Scheduler) -> Vec<NodeStatus> {
+    (0..config.nodes.len())
+        .map(|index| NodeStatus {
+            node: config.nodes[index].label(),
+            state: if scheduler.is_disabled(index) {
+                NodeState::Disabled
+            } else if scheduler.is_running(index) {
+                NodeState::Running
+            } else if scheduler.is_busy(index) {
+                NodeState::Queued
+            } else {
+                NodeState::Idle
+            },
+            succeeded: scheduler.result(index).map(|result| result.is_ok()),
+        })
+        .collect()
+}
+
+/// Every path in `map`, sorted, with the labels of the nodes owning it.
+pub fn tree(map: &Treemap) -> Vec<TreePath> {
+    let mut paths = map
+        .ids()
+        .map(|id| {
+            let path = map.full_path(id);
+            TreePath {
+                nodes: map
+                    .owners(&path)
+                    .iter()
+                    .map(|conf_node| conf_node.label())
+                    .collect(),
+                is_dir: map.is_dir(id),
+                path,
+            }
+        })
+        .collect::<Vec<_>>();
+    paths.sort_by(|a, b| a.path.cmp(&b.path));
+    return paths;
+}
+
+/// Requests read off the control socket, answered by whoever polls them.
+///
+/// Every connection gets a thread reading its requests and waiting for the
+/// answers, so a slow client never holds up the watcher. Dropping this stops
+/// the listener and the subscribers, and removes the socket file.
+#[derive(Debug)]
+pub struct Control {
+    path: PathBuf,
+    receiver: Receiver<(Request, Sender<Response>)>,
+    stopped: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+}
+
+/// How long a subscriber waits for an event before checking whether its
+/// client is still there.
+#[cfg(unix)]
+const SUBSCRIBER_INTERVAL: Duration = Duration::from_millis(100);
+
+impl Control {
+    /// Listens on a Unix socket at `path`, replacing a stale socket left
+    /// behind by a watcher that did not exit cleanly. Anything else already at
+    /// `path` is left alone.
+    #[cfg(unix)]
+    pub fn listen(path: &Path) -> Result<Self, ()> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                error!("Refusing to Replace {}: Not a Socket", path.display());
+                return Err(());
+            }
+            if UnixStream::connect(path).is_ok() {
+                error!("A Watcher is Already Listening on {}", path.display());
+                return Err(());
+            }
+            fs::remove_file(path)
+                .map_err(|err| error!("Failed to Remove Stale Socket {}: {err}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .map_err(|err| error!("Failed to Listen on {}: {err}", path.display()))?;
+        let (sender, receiver) = mpsc::channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stopped = stopped.clone();
+            move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let (sender, stopped) = (sender.clone(), stopped.clone());
+                    thread::spawn(move || serve(stream, sender, &stopped));
+                }
+            }
+        });
+        info!("Listening on {}", path.display());
+        return Ok(Self {
+            path: path.to_path_buf(),
+            receiver,
+            stopped,
+            listener: Some(handle),
+        });
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen(path: &Path) -> Result<Self, ()> {
+        error!(
+            "Failed to Listen on {}: Control Sockets Need Unix",
+            path.display()
+        );
+        return Err(());
+    }
+
+    /// The next request waiting for an answer, if any. The answer is sent
+    /// back on the returned sender.
+    pub fn next(&self) -> Option<(Request, Sender<Response>)> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            self.stopped.store(true, Ordering::SeqCst);
+            // The listener only looks at the flag once it accepts a connection.
+            let _ = UnixStream::connect(&self.path);
+            if let Some(listener) = self.listener.take() {
+                let _ = listener.join();
+            }
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Answers the requests of one client until it disconnects.
+#[cfg(unix)]
+fn serve(stream: UnixStream, sender: Sender<(Request, Sender<Response>)>, stopped: &AtomicBool) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = stream;
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                trace!("Control Request {request:?}");
+                let (reply, answer) = mpsc::channel();
+                if sender.send((request, reply)).is_err() {
+                    return;
+                }
+                let Ok(response) = answer.recv() else {
+                    return;
+                };
+                response
+            }
+            Err(err) => Response::Error {
+                message: format!("Invalid Request: {err}"),
+            },
+        };
+        let subscribed = response == Response::Subscribed;
+        if write_line(&mut writer, &response).is_err() {
+            return;
+        }
+        if subscribed {
+            let Ok(peer) = writer.try_clone() else {
+                return;
+            };
+            stream_events(&mut writer, peer, stopped);
+            return;
+        }
+    }
+}
+
+/// Writes every event to a subscribed client until it disconnects or the
+/// listener is stopped. The client is checked on whenever no event arrived
+/// for a while, so one leaving while the watcher is idle is noticed too.
+#[cfg(unix)]
+fn stream_events(writer: &mut impl Write, mut peer: UnixStream, stopped: &AtomicBool) {
+    let events = events::subscribe();
+    if peer
+        .set_read_timeout(Some(Duration::from_millis(1)))
+        .is_err()
+    {
+        return;
+    }
+    while !stopped.load(Ordering::SeqCst) {
+        match events.recv_timeout(SUBSCRIBER_INTERVAL) {
+            Ok(event) => {
+                if write_line(writer, &event).is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if is_closed(&mut peer) {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Whether the other end of `peer` hung up. Anything a subscribed client
+/// still sends is discarded.
+#[cfg(unix)]
+fn is_closed(peer: &mut UnixStream) -> bool {
+    let mut buffer = [0; 256];
+    return match peer.read(&mut buffer) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(err) => !matches!(
+            err.kind(),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+        ),
+    };
+}
+
+#[cfg(unix)]
+fn write_line(writer: &mut impl Write, value: &impl Serialize) -> std::io::Result<()> {
+    let line = serde_json::to_string(value)?;
+    writeln!(writer, "{line}")?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_control_parse_request() {
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"trigger","node":"api"}"#).unwrap(),
+            Request::Trigger {
+                node: String::from("api")
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"list"}"#).unwrap(),
+            Request::List
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"command":"restart"}"#).is_err());
+        assert_eq!(
+            serde_json::to_string(&Response::Nodes {
+                nodes: vec![NodeStatus {
+                    node: String::from("api"),
+                    state: NodeState::Running,
+                    succeeded: None,
+                }]
+            })
+            .unwrap(),
+            r#"{"response":"nodes","nodes":[{"node":"api","state":"running","succeeded":null}]}"#
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_control_socket() {
+        use crate::modules::events::Event;
+        use std::{env::temp_dir, thread::sleep, time::Duration};
+
+        let path = temp_dir().join("control_test.sock");
+        let _ = fs::remove_file(&path);
+        let control = Control::listen(&path).unwrap();
+        assert!(Control::listen(&path).is_err());
+
+        let client = thread::spawn({
+            let path = path.clone();
+            move || {
+                let mut stream = UnixStream::connect(path).unwrap();
+                let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+                let mut ask = |request: &str| {
+                    writeln!(stream, "{request}").unwrap();
+                    lines.next().unwrap().unwrap()
+                };
+                let answers = [
+                    ask("not json"),
+                    ask(r#"{"command":"pause"}"#),
+                    ask(r#"{"command":"subscribe"}"#),
+                ];
+                // Other tests emit events too, so skip ahead to ours.
+                let event = lines
+                    .map(|line| line.unwrap())
+                    .find(|line| line.contains("control_test"))
+                    .unwrap();
+                (answers, event)
+            }
+        });
+        let mut answered = 0;
+        while answered < 2 {
+            match control.next() {
+                Some((Request::Pause, reply)) => reply.send(Response::Ok).unwrap(),
+                Some((Request::Subscribe, reply)) => reply.send(Response::Subscribed).unwrap(),
+                Some((request, _)) => panic!("Unexpected {request:?}"),
+                None => {
+                    sleep(Duration::from_millis(10));
+                    continue;
+                }
+            }
+            answered += 1;
+        }
+        let event = Event::Started {
+            node: String::from("control_test"),
+            command: String::from("true"),
+            pid: None,
+        };
+        while !client.is_finished() {
+            events::emit(&event);
+            sleep(Duration::from_millis(10));
+        }
+        let (answers, line) = client.join().unwrap();
+        assert!(answers[0].starts_with(r#"{"response":"error","message":"Invalid Request"#));
+        assert_eq!(answers[1], r#"{"response":"ok"}"#);
+        assert_eq!(answers[2], r#"{"response":"subscribed"}"#);
+        assert_eq!(line, serde_json::to_string(&event).unwrap());
+
+        drop(control);
+        assert!(!path.exists());
+        assert!(UnixStream::connect(&path).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_control_subscriber_disconnect() {
+        use std::time::Instant;
+
+        let (server, client) = UnixStream::pair().unwrap();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let subscriber = thread::spawn({
+            let stopped = stopped.clone();
+            move || {
+                let peer = server.try_clone().unwrap();
+                stream_events(&mut &server, peer, &stopped);
+            }
+        });
+        drop(client);
+        let start = Instant::now();
+        while !subscriber.is_finished() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        subscriber.join().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_control_listen_not_socket() {
+        use std::env::temp_dir;
+
+        let path = temp_dir().join("control_test_notes.txt");
+        fs::write(&path, "notes").unwrap();
+        assert!(Control::listen(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "notes");
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,115 @@
+use log::error;
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex, OnceLock, PoisonError,
+    },
+};
+
+use super::command::Change;
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Where events go besides stdout, dropped once their receiver is.
+static SUBSCRIBERS: Mutex<Vec<Sender<Event>>> = Mutex::new(Vec::new());
+
+/// What happened while watching, printed as one JSON object per line with
+/// `--json`.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A batch of changes triggered a node.
+    Changed { node: String, changes: Vec<Change> },
+    Started {
+        node: String,
+        command: String,
+        pid: Option<u32>,
+    },
+    Exited {
+        node: String,
+        command: String,
+        status: String,
+        duration_ms: u128,
+    },
+    /// The command was stopped before it exited by itself.
+    Killed {
+        node: String,
+        command: String,
+        /// `timed_out`, `cancelled` or `stopped`.
+        reason: String,
+        duration_ms: u128,
+    },
+}
+
+/// Turns printing events on stdout on or off, only the first call counts.
+/// Command output then goes to stderr to keep stdout readable.
+pub fn init(enabled: bool) {
+    let _ = ENABLED.set(enabled);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.get().copied().unwrap_or_default()
+}
+
+/// Receives every event emitted from now on, whether or not they are printed.
+pub fn subscribe() -> Receiver<Event> {
+    let (sender, receiver) = mpsc::channel();
+    SUBSCRIBERS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(sender);
+    return receiver;
+}
+
+pub fn emit(event: &Event) {
+    SUBSCRIBERS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    if !is_enabled() {
+        return;
+    }
+    let Ok(line) = serde_json::to_string(event)
+        .map_err(|err| error!("Failed to Serialize Event {event:?}: {err}"))
+    else {
+        return;
+    };
+    let mut out = io::stdout().lock();
+    let _ = writeln!(out, "{line}").and_then(|_| out.flush());
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::modules::command::EventKind;
+
+    #[test]
+    fn test_events_serialize() {
+        let changed = Event::Changed {
+            node: String::from("api"),
+            changes: vec![Change {
+                path: PathBuf::from("/srv/api/main.go"),
+                kind: EventKind::Modified,
+            }],
+        };
+        assert_eq!(
+            serde_json::to_string(&changed).unwrap(),
+            r#"{"event":"changed","node":"api","changes":[{"path":"/srv/api/main.go","kind":"modified"}]}"#
+        );
+        let killed = Event::Killed {
+            node: String::from("api"),
+            command: String::from("go run ."),
+            reason: String::from("timed_out"),
+            duration_ms: 3000,
+        };
+        assert_eq!(
+            serde_json::to_string(&killed).unwrap(),
+            r#"{"event":"killed","node":"api","command":"go run .","reason":"timed_out","duration_ms":3000}"#
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use log::error;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::node::Node;
+
+/// The `depends_on` edges between config nodes, by index into the config.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Graph {
+    dependencies: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    /// Resolves `depends_on` by name, failing on duplicate or unknown names
+    /// and on cycles.
+    pub fn new(nodes: &[Box<Node>]) -> Result<Self, ()> {
+        let mut valid = true;
+        let mut names = BTreeMap::new();
+        for (index, conf_node) in nodes.iter().enumerate() {
+            if let Some(name) = &conf_node.name {
+                if names.insert(name.as_str(), index).is_some() {
+                    error!("Node Name {name} is Used more than Once");
+                    valid = false;
+                }
+            }
+        }
+
+        let mut dependencies = vec![Vec::new(); nodes.len()];
+        let mut dependents = vec![Vec::new(); nodes.len()];
+        for (index, conf_node) in nodes.iter().enumerate() {
+            for name in &conf_node.depends_on {
+                match names.get(name.as_str()) {
+                    Some(dependency) => {
+                        dependencies[index].push(*dependency);
+                        dependents[*dependency].push(index);
+                    }
+                    None => {
+                        error!("{}: Depends on Unknown Node {name}", conf_node.label());
+                        valid = false;
+                    }
+                }
+            }
+        }
+        if !valid {
+            return Err(());
+        }
+
+        let graph = Self {
+            dependencies,
+            dependents,
+        };
+        if let Some(cycle) = graph.cycle() {
+            let cycle = cycle
+                .iter()
+                .map(|index| nodes[*index].label())
+                .collect::<Vec<_>>();
+            error!("Nodes Depend on Each Other: {}", cycle.join(" -> "));
+            return Err(());
+        }
+        return Ok(graph);
+    }
+
+    /// A cycle of nodes depending on each other, if there is one, found by
+    /// sorting the nodes topologically until none are left without a
+    /// dependency waiting.
+    fn cycle(&self) -> Option<Vec<usize>> {
+        let mut remaining = self
+            .dependencies
+            .iter()
+            .map(|dependencies| dependencies.len())
+            .collect::<Vec<_>>();
+        let mut ready = (0..remaining.len())
+            .filter(|index| remaining[*index] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut sorted = 0;
+        while let Some(index) = ready.pop_first() {
+            sorted += 1;
+            for dependent in &self.dependents[index] {
+                remaining[*dependent] -= 1;
+                if remaining[*dependent] == 0 {
+                    ready.insert(*dependent);
+                }
+            }
+        }
+        if sorted == remaining.len() {
+            return None;
+        }
+
+        // Every node left over waits on another one left over, so following
+        // dependencies from any of them has to come back around.
+        let start = (0..remaining.len())
+            .find(|index| remaining[*index] > 0)
+            .unwrap_or_default();
+        let mut path = vec![start];
+        loop {
+            let current = *path.last().unwrap_or(&start);
+            let Some(next) = self.dependencies[current]
+                .iter()
+                .find(|dependency| remaining[**dependency] > 0)
+            else {
+                return Some(path);
+            };
+            if let Some(position) = path.iter().position(|index| index == next) {
+                let mut cycle = path.split_off(position);
+                cycle.push(*next);
+                cycle.reverse();
+                return Some(cycle);
+            }
+            path.push(*next);
+        }
+    }
+
+    pub fn dependencies(&self, index: usize) -> &[usize] {
+        &self.dependencies[index]
+    }
+
+    pub fn dependents(&self, index: usize) -> &[usize] {
+        &self.dependents[index]
+    }
+
+    /// Every node the node at `index` depends on, directly or through other
+    /// nodes.
+    pub fn ancestors(&self, index: usize) -> BTreeSet<usize> {
+        let mut ancestors = BTreeSet::new();
+        let mut pending = self.dependencies[index].clone();
+        while let Some(dependency) = pending.pop() {
+            if ancestors.insert(dependency) {
+                pending.extend(&self.dependencies[dependency]);
+            }
+        }
+        return ancestors;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn nodes(edges: &[(&str, &[&str])]) -> Vec<Box<Node>> {
+        edges
+            .iter()
+            .map(|(name, depends_on)| {
+                Box::from(Node {
+                    name: Some(name.to_string()),
+                    depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_graph_edges() {
+        let graph = Graph::new(&nodes(&[
+            ("server", &["proto", "db"]),
+            ("web", &["server"]),
+            ("proto", &[]),
+            ("db", &[]),
+        ]))
+        .unwrap();
+        assert_eq!(graph.cycle(), None);
+        assert_eq!(graph.dependents(2), &[0]);
+        assert_eq!(graph.dependencies(0), &[2, 3]);
+        assert_eq!(graph.ancestors(1), BTreeSet::from([0, 2, 3]));
+        assert_eq!(graph.ancestors(2), BTreeSet::new());
+    }
+
+    #[test]
+    fn test_graph_invalid() {
+        assert_eq!(
+            Graph::new(&nodes(&[
+                ("a", &["c"]),
+                ("b", &["a"]),
+                ("c", &["b"]),
+                ("d", &[])
+            ])),
+            Err(())
+        );
+        let graph = Graph {
+            dependencies: vec![vec![2], vec![0], vec![1], vec![]],
+            dependents: vec![vec![1], vec![2], vec![0], vec![]],
+        };
+        assert_eq!(graph.cycle(), Some(vec![0, 1, 2, 0]));
+        assert_eq!(Graph::new(&nodes(&[("a", &["a"])])), Err(()));
+        assert_eq!(Graph::new(&nodes(&[("a", &["missing"])])), Err(()));
+        assert_eq!(Graph::new(&nodes(&[("a", &[]), ("a", &[])])), Err(()));
+    }
+}
@@ -0,0 +1,156 @@
+use log::trace;
+use std::{
+    io::{self, IsTerminal, Read},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread::{self, sleep},
+    time::Duration,
+};
+
+/// What a key pressed while watching asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// `r`, run every node.
+    RerunAll,
+    /// `1` to `9`, run the node at that position in the config.
+    Rerun(usize),
+    /// `p`, stop or go back to reacting to changes.
+    Pause,
+    /// `c`, clear the terminal.
+    Clear,
+    /// `q`, stop everything and exit.
+    Quit,
+}
+
+impl Key {
+    pub fn parse(byte: u8) -> Option<Self> {
+        return match byte.to_ascii_lowercase() {
+            b'r' => Some(Self::RerunAll),
+            digit @ b'1'..=b'9' => Some(Self::Rerun((digit - b'1') as usize)),
+            b'p' => Some(Self::Pause),
+            b'c' => Some(Self::Clear),
+            b'q' => Some(Self::Quit),
+            _ => None,
+        };
+    }
+}
+
+/// Keys pressed in the terminal irminsul runs in.
+///
+/// The terminal is switched to reading single keystrokes without echoing
+/// them until this is dropped. Where that is not supported, keys are read
+/// once enter is pressed.
+#[derive(Debug)]
+pub struct Keys {
+    receiver: Receiver<Key>,
+    #[cfg(unix)]
+    _raw_mode: Option<RawMode>,
+}
+
+impl Keys {
+    /// Starts reading keys, if stdin is a terminal.
+    pub fn listen() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let is_terminal = io::stdin().is_terminal();
+        if is_terminal {
+            thread::spawn(move || {
+                for byte in io::stdin().lock().bytes() {
+                    let Ok(byte) = byte else {
+                        return;
+                    };
+                    let Some(key) = Key::parse(byte) else {
+                        trace!("Ignoring Key {byte}");
+                        continue;
+                    };
+                    if sender.send(key).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        return Self {
+            receiver,
+            #[cfg(unix)]
+            _raw_mode: is_terminal.then(RawMode::enable).flatten(),
+        };
+    }
+
+    /// The next key pressed within `timeout`.
+    pub fn next(&self, timeout: Duration) -> Option<Key> {
+        return match self.receiver.recv_timeout(timeout) {
+            Ok(key) => Some(key),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                sleep(timeout);
+                None
+            }
+        };
+    }
+}
+
+/// Keeps the terminal from buffering lines and echoing keys, restoring it
+/// when dropped.
+#[cfg(unix)]
+struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl std::fmt::Debug for RawMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawMode").finish_non_exhaustive()
+    }
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> Option<Self> {
+        // SAFETY: termios is plain data filled in by tcgetattr, and stdin is
+        // open for as long as the process runs.
+        unsafe {
+            let mut original = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                trace!("Failed to Get Terminal Attributes");
+                return None;
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                trace!("Failed to Set Terminal Attributes");
+                return None;
+            }
+            return Some(Self { original });
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes read in enable.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_keys_parse() {
+        assert_eq!(Key::parse(b'r'), Some(Key::RerunAll));
+        assert_eq!(Key::parse(b'R'), Some(Key::RerunAll));
+        assert_eq!(Key::parse(b'1'), Some(Key::Rerun(0)));
+        assert_eq!(Key::parse(b'9'), Some(Key::Rerun(8)));
+        assert_eq!(Key::parse(b'0'), None);
+        assert_eq!(Key::parse(b'p'), Some(Key::Pause));
+        assert_eq!(Key::parse(b'c'), Some(Key::Clear));
+        assert_eq!(Key::parse(b'q'), Some(Key::Quit));
+        assert_eq!(Key::parse(b'\n'), None);
+    }
+}
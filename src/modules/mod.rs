@@ -1,4 +1,16 @@
+pub mod cli;
+pub mod command;
 pub mod config;
+pub mod control;
+pub mod events;
+pub mod graph;
+pub mod keys;
 pub mod modes;
-mod node;
+pub mod node;
+pub mod output;
+pub mod runner;
+pub mod scheduler;
+pub mod signals;
+pub mod snapshot;
 pub mod treemap;
+pub mod watch;
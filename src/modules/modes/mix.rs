@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, time::Duration};
 
 use super::{Poll, PollMap};
-use crate::modules::treemap::Treemap;
+use crate::modules::treemap::{PointId, Treemap, ROOT};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Mix {
@@ -19,14 +19,23 @@ impl Default for Mix {
         }
     }
 }
+
+impl Mix {
+    /// The points a poll stats, walking down the branches of those that
+    /// changed. Directories with more branches than `branch_depth_ratio` per
+    /// level below the last fork are stat-ed as a whole.
+    pub fn polled_points(&self, path_map: &Treemap) -> Vec<PointId> {
+        <Treemap as PollMap<Mix>>::poll_map(path_map, ROOT, self.branch_depth_ratio, 0)
+    }
+}
+
 impl Poll for Mix {
-    fn poll(&self, path_map: &mut Box<Treemap>) -> Option<Vec<PathBuf>> {
-        let res: Vec<PathBuf> =
-            <Treemap as PollMap<Mix>>::poll_map(path_map, self.branch_depth_ratio, 0)
-                .par_iter_mut()
-                .map(|point| point.poll_branches())
-                .flatten()
-                .collect::<Vec<PathBuf>>();
+    fn poll(&self, path_map: &mut Treemap) -> Option<Vec<PathBuf>> {
+        let res: Vec<PathBuf> = self
+            .polled_points(path_map)
+            .into_iter()
+            .flat_map(|point| path_map.poll_branches(point))
+            .collect::<Vec<PathBuf>>();
         if !res.is_empty() {
             return Some(res);
         } else {
@@ -36,24 +45,25 @@ impl Poll for Mix {
 }
 
 impl PollMap<Mix> for Treemap {
-    fn poll_map(&mut self, branch_depth_ratio: f32, depth: usize) -> Vec<&mut Self> {
-        if self.branches.is_empty() {
-            return vec![self];
+    fn poll_map(&self, id: PointId, branch_depth_ratio: f32, depth: usize) -> Vec<PointId> {
+        let branches = self.branches(id).collect::<Vec<_>>();
+        if branches.is_empty() {
+            return vec![id];
         } else {
-            if (self.branches.len() as f32 / (depth + 1) as f32) > branch_depth_ratio {
-                return vec![self];
-            } else if self.branches.len() == 1 {
-                return self
-                    .branches
-                    .par_iter_mut()
-                    .map(|t| <Treemap as PollMap<Mix>>::poll_map(t, branch_depth_ratio, depth + 1))
+            if (branches.len() as f32 / (depth + 1) as f32) > branch_depth_ratio {
+                return vec![id];
+            } else if branches.len() == 1 {
+                return branches
+                    .into_par_iter()
+                    .map(|t| {
+                        <Treemap as PollMap<Mix>>::poll_map(self, t, branch_depth_ratio, depth + 1)
+                    })
                     .flatten()
                     .collect::<Vec<_>>();
             } else {
-                return self
-                    .branches
-                    .par_iter_mut()
-                    .map(|t| <Treemap as PollMap<Mix>>::poll_map(t, branch_depth_ratio, 0))
+                return branches
+                    .into_par_iter()
+                    .map(|t| <Treemap as PollMap<Mix>>::poll_map(self, t, branch_depth_ratio, 0))
                     .flatten()
                     .collect::<Vec<_>>();
             }
@@ -79,6 +89,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: None,
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         assert_eq!(Mix::default().poll(&mut map), None);
@@ -95,6 +106,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -119,6 +131,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -145,6 +158,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -177,6 +191,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
 
@@ -199,6 +214,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("**/*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -233,6 +249,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("**/*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -259,20 +276,21 @@ mod tests {
         fs::File::create(&file_path1).unwrap();
         fs::File::create(&file_path2).unwrap();
         fs::File::create(&file_path3).unwrap();
-        let mut map = Node {
+        let map = Node {
             root: dir.clone(),
             path_pattern: Some("**/*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
 
         {
-            let res = <Treemap as PollMap<Mix>>::poll_map(&mut map, 0.5, 0);
+            let res = <Treemap as PollMap<Mix>>::poll_map(&map, ROOT, 0.5, 0);
             assert_eq!(res.len(), 1);
         }
 
         {
-            let res = <Treemap as PollMap<Mix>>::poll_map(&mut map, 2.0, 0);
+            let res = <Treemap as PollMap<Mix>>::poll_map(&map, ROOT, 2.0, 0);
             assert_eq!(res.len(), 3);
         }
 
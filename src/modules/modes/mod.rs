@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use super::treemap::Treemap;
+use super::treemap::{PointId, Treemap};
 
 #[cfg(all(feature = "poll", feature = "notify"))]
 pub mod mix;
@@ -10,9 +10,9 @@ pub mod notify;
 pub mod poll;
 
 pub trait Poll {
-    fn poll(&self, files: &mut Box<Treemap>) -> Option<Vec<PathBuf>>;
+    fn poll(&self, files: &mut Treemap) -> Option<Vec<PathBuf>>;
 }
 
 trait PollMap<T> {
-    fn poll_map(&mut self, branch_depth_ratio: f32, depth: usize) -> Vec<&mut Self>;
+    fn poll_map(&self, id: PointId, branch_depth_ratio: f32, depth: usize) -> Vec<PointId>;
 }
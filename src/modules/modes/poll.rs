@@ -2,13 +2,13 @@ use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, time::Duration};
 
-use crate::modules::treemap::Treemap;
+use crate::modules::treemap::{PointId, Treemap, ROOT};
 
 use super::{Poll as PollTrait, PollMap};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Poll {
-    poll_rate: Duration,
+    pub poll_rate: Duration,
 }
 
 impl Default for Poll {
@@ -20,12 +20,18 @@ impl Default for Poll {
 }
 
 impl PollTrait for Poll {
-    fn poll(&self, path_map: &mut Box<Treemap>) -> Option<Vec<std::path::PathBuf>> {
-        let res = <Treemap as PollMap<Poll>>::poll_map(path_map, 0.0, 0)
-            .par_iter_mut()
-            .map(|point| point.poll_branches())
-            .flatten()
+    fn poll(&self, path_map: &mut Treemap) -> Option<Vec<PathBuf>> {
+        path_map.prune();
+        let leaves = <Treemap as PollMap<Poll>>::poll_map(path_map, ROOT, 0.0, 0)
+            .into_iter()
+            .filter(|point| !path_map.is_dir(*point))
+            .collect::<Vec<_>>();
+        let mut res = path_map
+            .poll_points(&leaves)
+            .into_iter()
+            .map(|point| path_map.full_path(point))
             .collect::<Vec<PathBuf>>();
+        res.append(&mut path_map.poll_structure());
         if !res.is_empty() {
             return Some(res);
         } else {
@@ -35,14 +41,15 @@ impl PollTrait for Poll {
 }
 
 impl PollMap<Poll> for Treemap {
-    fn poll_map(&mut self, _: f32, _: usize) -> Vec<&mut Self> {
-        if self.branches.is_empty() {
-            return vec![self];
+    fn poll_map(&self, id: PointId, _: f32, _: usize) -> Vec<PointId> {
+        if self.branches(id).len() == 0 {
+            return vec![id];
         } else {
             return self
-                .branches
-                .par_iter_mut()
-                .map(|t| <Treemap as PollMap<Poll>>::poll_map(t, 0.0, 0))
+                .branches(id)
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|t| <Treemap as PollMap<Poll>>::poll_map(self, t, 0.0, 0))
                 .flatten()
                 .collect::<Vec<_>>();
         }
@@ -56,7 +63,7 @@ mod tests {
     use std::{env::temp_dir, fs, io::Write, thread::sleep, time::Duration};
 
     use super::*;
-    use crate::modules::node::Node;
+    use crate::modules::{command::EventKind, node::Node};
 
     #[test]
     #[serial]
@@ -67,6 +74,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: None,
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         assert_eq!(Poll::default().poll(&mut map), None);
@@ -83,6 +91,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -107,6 +116,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -133,6 +143,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -165,6 +176,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
 
@@ -187,6 +199,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("**/*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -221,6 +234,7 @@ mod tests {
             root: dir.clone(),
             path_pattern: Some("**/*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
         sleep(Duration::from_millis(500));
@@ -247,18 +261,122 @@ mod tests {
         fs::File::create(&file_path1).unwrap();
         fs::File::create(&file_path2).unwrap();
         fs::File::create(&file_path3).unwrap();
-        let mut map = Node {
+        let map = Node {
             root: dir.clone(),
             path_pattern: Some("**/*".to_owned()),
             exec: None,
+            ..Default::default()
         }
         .build_treemap();
 
         {
-            let res = <Treemap as PollMap<Poll>>::poll_map(&mut map, 2.0, 0);
+            let res = <Treemap as PollMap<Poll>>::poll_map(&map, ROOT, 2.0, 0);
             assert_eq!(res.len(), 3);
         }
 
         let _ = fs::remove_dir_all(dir);
     }
+
+    #[test]
+    #[serial]
+    fn test_poll_poll_new_file() {
+        let dir = temp_dir().join("poll_poll_test7");
+        let _ = fs::create_dir_all(dir.clone());
+        let file_path1 = dir.join("file_poll7_poll1.txt");
+        let file_path2 = dir.join("file_poll7_poll2.txt");
+        let file_path3 = dir.join("file_poll7_poll3.md");
+        fs::File::create(&file_path1).unwrap();
+        let mut map = Node {
+            root: dir.clone(),
+            path_pattern: Some("*.txt".to_owned()),
+            exec: None,
+            ..Default::default()
+        }
+        .build_treemap();
+        sleep(Duration::from_millis(500));
+        fs::File::create(&file_path2).unwrap();
+        fs::File::create(&file_path3).unwrap();
+
+        assert_eq!(Poll::default().poll(&mut map), Some(vec![file_path2]));
+        assert_eq!(Poll::default().poll(&mut map), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_poll_poll_new_file_nested() {
+        let dir = temp_dir().join("poll_poll_test8");
+        let _ = fs::create_dir_all(dir.clone().join("inner"));
+        let file_path1 = dir.join("inner").join("file_poll8_poll1.txt");
+        let file_path2 = dir.join("inner").join("new").join("file_poll8_poll2.txt");
+        let mut map = Node {
+            root: dir.clone(),
+            path_pattern: Some("**/*.txt".to_owned()),
+            exec: None,
+            ..Default::default()
+        }
+        .build_treemap();
+        sleep(Duration::from_millis(500));
+        fs::File::create(&file_path1).unwrap();
+        assert_eq!(Poll::default().poll(&mut map), Some(vec![file_path1]));
+
+        sleep(Duration::from_millis(500));
+        let _ = fs::create_dir_all(dir.join("inner").join("new"));
+        fs::File::create(&file_path2).unwrap();
+        assert_eq!(Poll::default().poll(&mut map), Some(vec![file_path2]));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_poll_poll_removed_file() {
+        let dir = temp_dir().join("poll_poll_test9");
+        let _ = fs::create_dir_all(dir.clone());
+        let file_path1 = dir.join("file_poll9_poll1.txt");
+        let file_path2 = dir.join("file_poll9_poll2.txt");
+        fs::File::create(&file_path1).unwrap();
+        fs::File::create(&file_path2).unwrap();
+        let mut map = Node {
+            root: dir.clone(),
+            path_pattern: Some("*".to_owned()),
+            exec: None,
+            ..Default::default()
+        }
+        .build_treemap();
+        let _ = fs::remove_file(&file_path2);
+
+        assert_eq!(Poll::default().poll(&mut map), Some(vec![file_path2]));
+        assert_eq!(Poll::default().poll(&mut map), None);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_poll_poll_prune_removed() {
+        let dir = temp_dir().join("poll_poll_test10");
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::create_dir_all(dir.join("nested"));
+        let file_path = dir.join("nested").join("file_poll10_poll1.txt");
+        fs::File::create(&file_path).unwrap();
+        let conf_node = Node {
+            root: dir.clone(),
+            path_pattern: Some("**/*.txt".to_owned()),
+            exec: None,
+            ..Default::default()
+        };
+        let mut map = conf_node.build_treemap();
+        let _ = fs::remove_file(&file_path);
+
+        // The removed file stays in the tree for the poll reporting it, so
+        // the change can still be routed to the node owning it.
+        let paths = Poll::default().poll(&mut map).unwrap();
+        assert_eq!(paths, vec![file_path.clone()]);
+        assert_eq!(map.event_kind(&file_path), EventKind::Removed);
+        assert_eq!(map.route(&paths).len(), 1);
+
+        assert_eq!(Poll::default().poll(&mut map), None);
+        assert_eq!(map.find(&file_path), None);
+        assert!(map.find(&dir.join("nested")).is_some());
+        let _ = fs::remove_dir_all(dir);
+    }
 }
@@ -1,104 +1,629 @@
+use log::{error, trace};
 use normpath::PathExt;
-use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::{collections::VecDeque, ffi::OsStr, path::PathBuf};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    env,
+    fmt::Display,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use subprocess::{ExitStatus, PopenConfig};
 
-use super::treemap::Treemap;
+use super::{
+    command::{expand, Change, Shell},
+    treemap::Treemap,
+};
+use crate::utils::env::{interpolate, parse_env_file};
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct Node {
+    /// How other nodes, logs and the CLI refer to the node.
+    pub name: Option<String>,
     pub root: PathBuf,
     pub path_pattern: Option<String>,
+    /// Patterns for paths below the root to leave out along with everything
+    /// below them. They match at any depth, so `target` leaves out every
+    /// `target` directory.
+    #[serde(default)]
+    pub ignore: Vec<String>,
     pub exec: Option<String>,
+    /// Run `exec` once per changed file instead of once per batch.
+    #[serde(default)]
+    pub per_file: bool,
+    /// Directory `exec` runs in, relative to the root. Defaults to the
+    /// directory irminsul was started from.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// Variables set for `exec`. Values may refer to `${VAR}`s from the env
+    /// file or the environment irminsul runs in.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// A `.env` file loaded before `env`, relative to the root.
+    #[serde(default)]
+    pub env_file: Option<PathBuf>,
+    #[serde(default)]
+    pub shell: Shell,
+    /// Start `exec` with only the variables above instead of inheriting
+    /// irminsul's environment.
+    #[serde(default)]
+    pub clear_env: bool,
+    /// Commands run in order instead of `exec`, stopping at the first that
+    /// fails.
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    /// Names of the nodes that have to finish before this one runs. The node
+    /// also runs whenever one of them finishes successfully.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Queued nodes with a higher priority run first.
+    #[serde(default)]
+    pub priority: i32,
+    /// Seconds each step may run for, unless the step sets its own.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Exit codes counted as success, only 0 when empty.
+    #[serde(default)]
+    pub success_codes: Vec<u32>,
+    #[serde(default)]
+    pub on_failure: OnFailure,
+    /// Clear the terminal before each run.
+    #[serde(default)]
+    pub clear_screen: bool,
+    /// Run once when watching starts, before anything changed.
+    #[serde(default)]
+    pub run_on_start: bool,
+    /// Ring the terminal bell along with the status line after each run.
+    #[serde(default)]
+    pub bell: bool,
+    /// Patterns for files the node's commands write, relative to the root.
+    /// Changes to them never trigger the node itself.
+    #[serde(default)]
+    pub outputs: Vec<String>,
+    /// Skip changes made while the node runs, eg. by its own codegen or
+    /// formatter.
+    #[serde(default)]
+    pub suppress_own_changes: bool,
+    /// How many times in a row the node may be triggered again by changes
+    /// made while it ran before those changes are skipped, unlimited when
+    /// unset.
+    #[serde(default)]
+    pub max_retriggers: Option<u32>,
+    /// The root and patterns above, compiled on first use.
+    #[serde(skip)]
+    pub compiled: Compiled,
 }
 
-impl Node {
-    #[cfg(target_family = "unix")]
-    fn build_paths(&self) -> Vec<PathBuf> {
-        if self.path_pattern.is_some() {
-            globmatch::Builder::new(
-                self.path_pattern
-                    .clone()
-                    .expect("Path Pattern is Missing")
-                    .as_str(),
-            )
-            .build(self.root.clone())
-            .map_err(|err| eprintln!("Failed to Build Glob Pattern: {err}"))
-            .unwrap()
-            .into_iter()
-            .flatten()
-            .map(|f| {
-                f.normalize()
-                    .expect("Failed to Normalize Path")
-                    .into_path_buf()
-            })
-            .collect()
-        } else {
-            vec![self.root.clone()]
+/// What matching paths against a node needs, resolved and compiled once
+/// instead of for every path.
+#[derive(Debug)]
+struct Matchers {
+    /// The root and patterns compiled, to tell whether the node changed since.
+    source: (PathBuf, Option<String>, Vec<String>, Vec<String>),
+    root: PathBuf,
+    glob: Option<globset::GlobMatcher>,
+    /// Each ignore pattern that compiled, along with the pattern.
+    ignore: Vec<(String, globset::GlobSet)>,
+    outputs: Vec<globset::GlobMatcher>,
+}
+
+/// A node's [`Matchers`], shared between its clones. Nodes compare, hash and
+/// sort the same whether or not they were compiled yet.
+#[derive(Clone, Debug, Default)]
+pub struct Compiled(OnceLock<Arc<Matchers>>);
+
+impl PartialEq for Compiled {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Compiled {}
+
+impl PartialOrd for Compiled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Compiled {
+    fn cmp(&self, _: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl Hash for Compiled {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+/// What to do once a run of a node fails.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OnFailure {
+    /// Log it and carry on. Dependents are skipped either way.
+    #[default]
+    Ignore,
+    /// Run the pipeline again up to `attempts` times, waiting `backoff`
+    /// milliseconds before the first retry and twice as long before each
+    /// one after.
+    Retry { attempts: u32, backoff: u64 },
+    /// Stop triggering the node. Irminsul exits once no node is left.
+    StopWatching,
+    /// Run this command with the failed batch.
+    Run(String),
+}
+
+/// Whether a path matches a node and what decided it, see [`Node::explain`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The node has no path pattern and the path is its root.
+    Root,
+    /// The node has no path pattern, so it only owns its root.
+    NotRoot {
+        root: PathBuf,
+    },
+    OutsideRoot {
+        root: PathBuf,
+    },
+    /// `ignored`, the path or a directory it is in, matches `pattern`.
+    Ignored {
+        pattern: String,
+        ignored: PathBuf,
+    },
+    NoMatch {
+        pattern: String,
+        rel: PathBuf,
+    },
+    Matches {
+        pattern: String,
+        rel: PathBuf,
+    },
+}
+
+impl Verdict {
+    pub fn is_match(&self) -> bool {
+        matches!(self, Self::Root | Self::Matches { .. })
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Root => write!(f, "matches, it is the root and there is no path_pattern"),
+            Self::NotRoot { root } => write!(
+                f,
+                "no match, without a path_pattern only the root {} is watched",
+                root.display()
+            ),
+            Self::OutsideRoot { root } => {
+                write!(f, "no match, it is outside the root {}", root.display())
+            }
+            Self::Ignored { pattern, ignored } => {
+                write!(f, "no match, {} is ignored by {pattern}", ignored.display())
+            }
+            Self::NoMatch { pattern, rel } => {
+                write!(f, "no match, {} does not match {pattern}", rel.display())
+            }
+            Self::Matches { pattern, rel } => {
+                write!(f, "matches, {} matches {pattern}", rel.display())
+            }
         }
     }
+}
 
-    #[cfg(target_family = "windows")]
-    fn build_paths(&self) -> Vec<PathBuf> {
-        if self.path_pattern.is_some() {
-            globmatch::Builder::new(
-                self.path_pattern
-                    .clone()
-                    .expect("Path Pattern is Missing")
-                    .as_str(),
-            )
-            .build(self.root.clone())
-            .map_err(|err| eprintln!("Failed to Build Glob Pattern: {err}"))
-            .unwrap()
-            .into_iter()
-            .flatten()
-            .map(|f| {
-                PathBuf::from("WinRoot").join(
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct Step {
+    pub exec: String,
+    /// Seconds the step may run before it is killed and counts as failed,
+    /// the node's `timeout` by default.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Leave the step running once started, eg. a server, and restart it on
+    /// the next change. Only the last step can be long running.
+    #[serde(default)]
+    pub long_running: bool,
+}
+
+impl Node {
+    pub fn build_paths(&self) -> Vec<PathBuf> {
+        if let Some(pattern) = &self.path_pattern {
+            let Ok(matcher) = globmatch::Builder::new(pattern)
+                .build(self.root.clone())
+                .map_err(|err| error!("Failed to Build Glob Pattern: {err}"))
+            else {
+                return Vec::new();
+            };
+            matcher
+                .into_iter()
+                .filter_entry(|path| !self.is_ignored(path))
+                .flatten()
+                .map(|f| {
                     f.normalize()
                         .expect("Failed to Normalize Path")
-                        .into_path_buf(),
-                )
-            })
-            .collect()
+                        .into_path_buf()
+                })
+                .collect()
         } else {
-            vec![PathBuf::from("WinRoot").join(self.root.clone())]
+            vec![self.normalized_root()]
+        }
+    }
+
+    /// The root as it appears in the treemap. Roots that do not exist yet
+    /// cannot be resolved and are only made absolute.
+    pub fn normalized_root(&self) -> PathBuf {
+        self.matchers().root.clone()
+    }
+
+    fn resolve_root(&self) -> PathBuf {
+        self.root
+            .normalize()
+            .map(|root| root.into_path_buf())
+            .or_else(|_| std::path::absolute(&self.root))
+            .unwrap_or_else(|_| self.root.clone())
+    }
+
+    /// The root and patterns compiled on first use. A node changed since is
+    /// compiled again, without replacing what was compiled first.
+    fn matchers(&self) -> Arc<Matchers> {
+        let source = || {
+            (
+                self.root.clone(),
+                self.path_pattern.clone(),
+                self.ignore.clone(),
+                self.outputs.clone(),
+            )
+        };
+        let matchers = self.compiled.0.get_or_init(|| self.compile(source()));
+        let (root, path_pattern, ignore, outputs) = &matchers.source;
+        if *root == self.root
+            && *path_pattern == self.path_pattern
+            && *ignore == self.ignore
+            && *outputs == self.outputs
+        {
+            return matchers.clone();
         }
+        return self.compile(source());
     }
 
-    fn build_treemap_inner(
+    fn compile(
         &self,
-        path_segments: &mut VecDeque<&OsStr>,
-        prev_segment: PathBuf,
-    ) -> Box<Treemap> {
-        let segment = path_segments
-            .pop_front()
-            .expect("Failed to Pop Front Element");
-        let mut set = Vec::new();
-        if !path_segments.is_empty() {
-            let branch = self.build_treemap_inner(path_segments, prev_segment.join(segment));
-            set.push(branch);
-        }
-        let node = Box::from(Treemap::new(segment.into(), set, prev_segment));
-        return node;
-    }
-    pub fn build_treemap(&self) -> Box<Treemap> {
-        let paths = self.build_paths();
-        let mut maps = paths
-            .par_iter()
-            .map(|path| {
-                let mut path_segs = path.iter().collect::<VecDeque<_>>();
-                self.build_treemap_inner(&mut path_segs, PathBuf::new())
-                // path_segs.iter().map(|seg| Treemap::new(seg.into(), 0, branches, prev_path))
+        source: (PathBuf, Option<String>, Vec<String>, Vec<String>),
+    ) -> Arc<Matchers> {
+        let glob = self.path_pattern.as_deref().and_then(|pattern| {
+            globmatch::Builder::new(pattern)
+                .build_glob()
+                .map(|glob| glob.matcher)
+                .map_err(|err| error!("Failed to Build Glob Pattern: {err}"))
+                .ok()
+        });
+        let ignore = self
+            .ignore
+            .iter()
+            .filter_map(|pattern| {
+                globmatch::Builder::new(pattern)
+                    .build_glob_set()
+                    .map(|glob| (pattern.clone(), glob.matcher))
+                    .map_err(|err| error!("Failed to Build Ignore Pattern: {err}"))
+                    .ok()
             })
-            .collect::<Vec<_>>();
-        let parent = maps.pop().expect("Must have atleast one path");
+            .collect();
+        // Broken output patterns are reported by validate.
+        let outputs = self
+            .outputs
+            .iter()
+            .filter_map(|pattern| {
+                globmatch::Builder::new(pattern)
+                    .build_glob()
+                    .map(|glob| glob.matcher)
+                    .ok()
+            })
+            .collect();
+        return Arc::new(Matchers {
+            source,
+            root: self.resolve_root(),
+            glob,
+            ignore,
+            outputs,
+        });
+    }
+
+    /// Whether `path` is one of the node's declared outputs.
+    pub fn is_output(&self, path: &Path) -> bool {
+        let matchers = self.matchers();
+        let Ok(rel) = path.strip_prefix(&matchers.root) else {
+            return false;
+        };
+        return matchers.outputs.iter().any(|glob| glob.is_match(rel));
+    }
+
+    /// Whether `path` or a directory it is in, up to the root, is ignored.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.ignored_by(path).is_some()
+    }
+
+    /// The ignore pattern leaving out `path`, along with the part of it
+    /// relative to the root that matched.
+    fn ignored_by(&self, path: &Path) -> Option<(String, PathBuf)> {
+        if self.ignore.is_empty() {
+            return None;
+        }
+        let matchers = self.matchers();
+        let rel = path
+            .strip_prefix(&matchers.root)
+            .or_else(|_| path.strip_prefix(&self.root))
+            .unwrap_or(path);
+        return rel
+            .ancestors()
+            .filter(|rel| !rel.as_os_str().is_empty())
+            .find_map(|rel| {
+                let (pattern, _) = matchers
+                    .ignore
+                    .iter()
+                    .find(|(_, glob)| glob.is_match(rel))?;
+                Some((pattern.clone(), rel.to_path_buf()))
+            });
+    }
+
+    /// Whether the pattern can match anything below `rel_dir`, ie. whether new
+    /// entries in it need to be picked up. Patterns with `**` reach every
+    /// directory, otherwise only as deep as the pattern has components.
+    fn descends_into(pattern: &str, rel_dir: &Path) -> bool {
+        pattern.contains("**")
+            || rel_dir.components().count() < Path::new(pattern).components().count()
+    }
+
+    pub fn tracks_dir(&self, dir: &Path) -> bool {
+        match (&self.path_pattern, dir.strip_prefix(&self.matchers().root)) {
+            (Some(pattern), Ok(rel)) => Self::descends_into(pattern, rel) && !self.is_ignored(dir),
+            _ => false,
+        }
+    }
 
-        let mut parent = parent.as_ref().clone();
-        while !maps.is_empty() {
-            let other = maps.pop().unwrap();
-            parent.merge(&mut other.as_ref().clone());
+    pub fn matches(&self, path: &Path) -> bool {
+        let matchers = self.matchers();
+        match &matchers.glob {
+            Some(glob) => path
+                .strip_prefix(&matchers.root)
+                .map(|rel| glob.is_match(rel) && !self.is_ignored(path))
+                .unwrap_or(false),
+            None => path == matchers.root,
         }
-        Box::from(parent)
+    }
+
+    /// Why `path` does or does not match the node, following the same rules
+    /// as [`Node::matches`].
+    pub fn explain(&self, path: &Path) -> Verdict {
+        let matchers = self.matchers();
+        let root = matchers.root.clone();
+        let Some(glob) = &matchers.glob else {
+            return match path == root {
+                true => Verdict::Root,
+                false => Verdict::NotRoot { root },
+            };
+        };
+        let pattern = self.path_pattern.clone().unwrap_or_default();
+        let Ok(rel) = path.strip_prefix(&root) else {
+            return Verdict::OutsideRoot { root };
+        };
+        let rel = rel.to_path_buf();
+        if let Some((pattern, ignored)) = self.ignored_by(path) {
+            return Verdict::Ignored { pattern, ignored };
+        }
+        return match glob.is_match(&rel) {
+            true => Verdict::Matches { pattern, rel },
+            false => Verdict::NoMatch { pattern, rel },
+        };
+    }
+
+    /// Walks `dir` and returns the files matching the path pattern, along
+    /// with every directory the pattern can still reach below it.
+    pub fn scan(&self, dir: &Path) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        let matchers = self.matchers();
+        let (Some(pattern), Some(glob)) = (self.path_pattern.as_deref(), &matchers.glob) else {
+            return (files, dirs);
+        };
+        let root = &matchers.root;
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            let Ok(entries) = fs::read_dir(&current) else {
+                trace!("Failed to Read Dir {}", current.display());
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(rel) = path.strip_prefix(root) else {
+                    continue;
+                };
+                if self.is_ignored(&path) {
+                    continue;
+                }
+                if glob.is_match(rel) {
+                    files.push(path.clone());
+                }
+                if entry.file_type().map(|t| t.is_dir()).unwrap_or(false)
+                    && Self::descends_into(pattern, rel)
+                {
+                    dirs.push(path.clone());
+                    pending.push(path);
+                }
+            }
+        }
+        (files, dirs)
+    }
+
+    /// Directories watched for structural changes without being owned by
+    /// the node: its root and everything the pattern can reach below it.
+    pub fn build_dirs(&self) -> Vec<PathBuf> {
+        if self.path_pattern.is_none() {
+            return Vec::new();
+        }
+        let root = self.normalized_root();
+        if !root.is_dir() {
+            return vec![root];
+        }
+        let (_, mut dirs) = self.scan(&root);
+        dirs.push(root);
+        dirs
+    }
+
+    /// The name, or the root for unnamed nodes.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self.root.display().to_string(),
+        }
+    }
+
+    pub fn working_dir(&self) -> Option<PathBuf> {
+        self.cwd
+            .as_ref()
+            .map(|cwd| self.normalized_root().join(cwd))
+    }
+
+    /// The variables from the env file followed by the interpolated `env`.
+    pub fn environment(&self) -> Result<Vec<(String, String)>, ()> {
+        let mut vars = match &self.env_file {
+            Some(env_file) => parse_env_file(&self.normalized_root().join(env_file))?,
+            None => Vec::new(),
+        };
+        for (key, value) in &self.env {
+            let value = interpolate(value, |name| {
+                vars.iter()
+                    .rev()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.clone())
+                    .or_else(|| env::var(name).ok())
+            })?;
+            vars.push((key.clone(), value));
+        }
+        return Ok(vars);
+    }
+
+    /// The arguments and config to start `command` with, set up with the
+    /// node's shell, working directory and environment.
+    pub fn process(&self, command: &str) -> Result<(Vec<String>, PopenConfig), ()> {
+        let argv = self.shell.argv(command)?;
+        let mut env = match self.clear_env {
+            true => Vec::new(),
+            false => PopenConfig::current_env(),
+        };
+        env.extend(
+            self.environment()?
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        let config = PopenConfig {
+            env: Some(env),
+            cwd: self.working_dir().map(PathBuf::into_os_string),
+            ..Default::default()
+        };
+        return Ok((argv, config));
+    }
+
+    /// Checks what can be checked before anything runs, logging every problem.
+    pub fn validate(&self) -> Result<(), ()> {
+        let mut valid = true;
+        let label = self.label();
+        let matchers = self.matchers();
+        if self.path_pattern.is_some() && matchers.glob.is_none() {
+            valid = false;
+        }
+        if matchers.ignore.len() < self.ignore.len() {
+            valid = false;
+        }
+        for pattern in &self.outputs {
+            if let Err(err) = globmatch::Builder::new(pattern).build_glob() {
+                error!("{label}: Failed to Build Output Pattern: {err}");
+                valid = false;
+            }
+        }
+        if let Some(cwd) = self.working_dir() {
+            if !cwd.is_dir() {
+                error!("{label}: Working Dir {} is not a Directory", cwd.display());
+                valid = false;
+            }
+        }
+        if self.environment().is_err() {
+            error!("{label}: Failed to Resolve the Environment");
+            valid = false;
+        }
+        if self.exec.is_some() && !self.steps.is_empty() {
+            error!("{label}: Set Either exec or steps, not Both");
+            valid = false;
+        }
+        let pipeline = self.pipeline();
+        for (index, step) in pipeline.iter().enumerate() {
+            if self.shell.argv(&step.exec).is_err() {
+                error!("{label}: Failed to Build the Command {}", step.exec);
+                valid = false;
+            }
+            if step.long_running && (index + 1 < pipeline.len() || self.per_file) {
+                error!("{label}: Only the Last Step of a Batch Pipeline can be Long Running");
+                valid = false;
+            }
+        }
+        if let OnFailure::Run(command) = &self.on_failure {
+            if self.shell.argv(command).is_err() {
+                error!("{label}: Failed to Build the Command {command}");
+                valid = false;
+            }
+        }
+        return match valid {
+            true => Ok(()),
+            false => Err(()),
+        };
+    }
+
+    pub fn is_success(&self, status: &ExitStatus) -> bool {
+        match status {
+            ExitStatus::Exited(code) if self.success_codes.is_empty() => *code == 0,
+            ExitStatus::Exited(code) => self.success_codes.contains(code),
+            _ => false,
+        }
+    }
+
+    pub fn step_timeout(&self, step: &Step) -> Option<Duration> {
+        step.timeout.or(self.timeout).map(Duration::from_secs)
+    }
+
+    /// Splits changes into the batches `exec` runs for, one per change in
+    /// per-file mode.
+    pub fn batches<'a>(&self, changes: &'a [Change]) -> Vec<&'a [Change]> {
+        if self.per_file && !changes.is_empty() {
+            return changes.chunks(1).collect();
+        }
+        return vec![changes];
+    }
+
+    /// The steps run on a change, `exec` being a pipeline of one.
+    pub fn pipeline(&self) -> Vec<Step> {
+        match &self.exec {
+            Some(exec) => vec![Step {
+                exec: exec.clone(),
+                ..Default::default()
+            }],
+            None => self.steps.clone(),
+        }
+    }
+
+    /// The commands to run for a batch of changes, in order.
+    pub fn commands(&self, changes: &[Change]) -> Vec<String> {
+        let pipeline = self.pipeline();
+        return self
+            .batches(changes)
+            .into_iter()
+            .flat_map(|batch| pipeline.iter().map(|step| expand(&step.exec, self, batch)))
+            .collect();
+    }
+
+    pub fn build_treemap(&self) -> Treemap {
+        Treemap::from_nodes(&[Box::from(self.clone())])
     }
 }
 
@@ -116,23 +641,23 @@ mod tests {
             exec: None,
             root: PathBuf::from("/home/bob_ross"),
             path_pattern: None,
+            ..Default::default()
         };
-        let mut branch_inner = Vec::new();
-        branch_inner.push(Box::from(Treemap::new(
+        let branch_inner = vec![Treemap::new(
             PathBuf::from("bob_ross"),
             Vec::new(),
             PathBuf::from("/home"),
-        )));
-        let mut branch = Vec::new();
-        branch.push(Box::from(Treemap::new(
+        )];
+        let branch = vec![Treemap::new(
             PathBuf::from("home"),
             branch_inner,
             PathBuf::from("/"),
-        )));
-        assert_eq!(
-            conf_node.build_treemap(),
-            Box::from(Treemap::new(PathBuf::from("/"), branch, PathBuf::new()))
-        );
+        )];
+        let mut treemap = Treemap::new(PathBuf::from("/"), branch, PathBuf::new());
+        let id = treemap.find(Path::new("/home/bob_ross")).unwrap();
+        let index = treemap.add_conf_node(Box::from(conf_node.clone()));
+        treemap.link_conf_node(id, index);
+        assert_eq!(conf_node.build_treemap(), treemap);
     }
 
     #[test]
@@ -148,33 +673,38 @@ mod tests {
         let _ = fs::File::create(&file2_path)
             .map_err(|err| error!("Failed to Create Temp File {}: {err}", file2_path.display()));
 
-        let mut temp_treemap = Node {
-            root: temp_dir.clone(),
-            path_pattern: None,
-            exec: None,
-        }
-        .build_treemap();
-        let mut branch_inner = Vec::new();
-        branch_inner.push(Box::from(Treemap::new(
-            PathBuf::from("the old mill.png"),
-            Vec::new(),
-            temp_dir.clone(),
-        )));
-        branch_inner.push(Box::from(Treemap::new(
-            PathBuf::from("mountain retreat.png"),
-            Vec::new(),
-            temp_dir.clone(),
-        )));
-        temp_treemap.branches[0].branches[0].branches = branch_inner;
+        let mut temp_treemap = Treemap::from_paths(std::slice::from_ref(&temp_dir));
+        let branch_inner = vec![
+            Treemap::new(
+                PathBuf::from("the old mill.png"),
+                Vec::new(),
+                temp_dir.clone(),
+            ),
+            Treemap::new(
+                PathBuf::from("mountain retreat.png"),
+                Vec::new(),
+                temp_dir.clone(),
+            ),
+        ];
+        temp_treemap.merge(Treemap::new(
+            PathBuf::from(temp_dir.file_name().unwrap()),
+            branch_inner,
+            temp_dir.parent().unwrap().to_path_buf(),
+        ));
 
         let conf_node = Node {
             exec: None,
             root: temp_dir.clone(),
             path_pattern: Some(String::from("*.png")),
+            ..Default::default()
+        };
+        let index = temp_treemap.add_conf_node(Box::from(conf_node.clone()));
+        for file in [&file1_path, &file2_path] {
+            let id = temp_treemap.find(file).unwrap();
+            temp_treemap.link_conf_node(id, index);
         }
-        .build_treemap();
 
-        assert_eq!(conf_node, temp_treemap);
+        assert_eq!(conf_node.build_treemap(), temp_treemap);
         let _ = fs::remove_dir_all(temp_dir);
     }
 
@@ -182,7 +712,7 @@ mod tests {
     #[serial]
     fn test_conf_node_multiple_files_one_nested_no_match() {
         let temp_dir = temp_dir().join("bob_ross2");
-        let _ = fs::create_dir_all(&temp_dir.join("WIP"))
+        let _ = fs::create_dir_all(temp_dir.join("WIP"))
             .map_err(|err| error!("Failed to Create Temp Dir {err}"));
         let file1_path = temp_dir.join("the old mill.png");
         let file2_path = temp_dir.join("mountain retreat.png");
@@ -194,39 +724,40 @@ mod tests {
         let _ = fs::File::create(&file3_path)
             .map_err(|err| error!("Failed to Create Temp File {}: {err}", file3_path.display()));
 
-        let mut temp_treemap = Node {
-            root: temp_dir.clone(),
-            path_pattern: None,
-            exec: None,
-        }
-        .build_treemap();
-        let mut branch_inner = Vec::new();
-        branch_inner.push(Box::from(Treemap::new(
-            PathBuf::from("the old mill.png"),
-            Vec::new(),
-            temp_dir.clone(),
-        )));
-        branch_inner.push(Box::from(Treemap::new(
-            PathBuf::from("mountain retreat.png"),
-            Vec::new(),
-            temp_dir.clone(),
-        )));
-        branch_inner.push(Box::from(Treemap::new(
-            PathBuf::from("WIP"),
-            vec![Box::from(Treemap::new(
-                PathBuf::from("Wilderness Day.png"),
+        let mut temp_treemap = Treemap::from_paths(std::slice::from_ref(&temp_dir));
+        let branch_inner = vec![
+            Treemap::new(
+                PathBuf::from("the old mill.png"),
                 Vec::new(),
-                temp_dir.clone().join("WIP"),
-            ))],
-            temp_dir.clone(),
-        )));
-        temp_treemap.branches[0].branches[0].branches = branch_inner;
+                temp_dir.clone(),
+            ),
+            Treemap::new(
+                PathBuf::from("mountain retreat.png"),
+                Vec::new(),
+                temp_dir.clone(),
+            ),
+            Treemap::new(
+                PathBuf::from("WIP"),
+                vec![Treemap::new(
+                    PathBuf::from("Wilderness Day.png"),
+                    Vec::new(),
+                    temp_dir.clone().join("WIP"),
+                )],
+                temp_dir.clone(),
+            ),
+        ];
+        temp_treemap.merge(Treemap::new(
+            PathBuf::from(temp_dir.file_name().unwrap()),
+            branch_inner,
+            temp_dir.parent().unwrap().to_path_buf(),
+        ));
 
         {
             let conf_node = Node {
                 exec: None,
                 root: temp_dir.clone(),
                 path_pattern: Some(String::from("*.png")),
+                ..Default::default()
             }
             .build_treemap();
 
@@ -238,11 +769,150 @@ mod tests {
                 exec: None,
                 root: temp_dir.clone(),
                 path_pattern: Some(String::from("**/*.png")),
+                ..Default::default()
+            };
+            let index = temp_treemap.add_conf_node(Box::from(conf_node.clone()));
+            for file in [&file1_path, &file2_path, &file3_path] {
+                let id = temp_treemap.find(file).unwrap();
+                temp_treemap.link_conf_node(id, index);
             }
-            .build_treemap();
 
-            assert_eq!(conf_node, temp_treemap);
+            assert_eq!(conf_node.build_treemap(), temp_treemap);
         }
         let _ = fs::remove_dir_all(temp_dir);
     }
+
+    #[test]
+    #[serial]
+    fn test_conf_node_ignore() {
+        let temp_dir = temp_dir().join("bob_ross3");
+        let _ = fs::remove_dir_all(&temp_dir);
+        let _ = fs::create_dir_all(temp_dir.join("src"));
+        let _ = fs::create_dir_all(temp_dir.join("target").join("debug"));
+        let main_path = temp_dir.join("src").join("main.rs");
+        let build_path = temp_dir.join("target").join("debug").join("build.rs");
+        let manifest_path = temp_dir.join("Cargo.toml");
+        for path in [&main_path, &build_path, &manifest_path] {
+            fs::File::create(path).unwrap();
+        }
+
+        let conf_node = Node {
+            root: temp_dir.clone(),
+            path_pattern: Some(String::from("{**/*.rs,Cargo.toml}")),
+            ignore: vec![String::from("target")],
+            ..Default::default()
+        };
+        assert_eq!(conf_node.validate(), Ok(()));
+        let mut paths = conf_node.build_paths();
+        paths.sort();
+        assert_eq!(paths, vec![manifest_path.clone(), main_path.clone()]);
+        assert!(conf_node.matches(&main_path));
+        assert!(!conf_node.matches(&build_path));
+        assert!(conf_node.is_ignored(&temp_dir.join("target")));
+        assert!(!conf_node.tracks_dir(&temp_dir.join("target").join("debug")));
+        let (mut files, dirs) = conf_node.scan(&temp_dir);
+        files.sort();
+        assert_eq!(files, vec![manifest_path, main_path]);
+        assert_eq!(dirs, vec![temp_dir.join("src")]);
+
+        let conf_node = Node {
+            ignore: vec![String::from("[")],
+            ..conf_node
+        };
+        assert_eq!(conf_node.validate(), Err(()));
+        let conf_node = Node {
+            path_pattern: Some(String::from("[")),
+            ignore: Vec::new(),
+            ..conf_node
+        };
+        assert_eq!(conf_node.build_paths(), Vec::<PathBuf>::new());
+        let _ = fs::remove_dir_all(temp_dir);
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_conf_node_compiled() {
+        let conf_node = Node {
+            root: PathBuf::from("/home/bob_ross"),
+            path_pattern: Some(String::from("*.png")),
+            ..Default::default()
+        };
+        let path = PathBuf::from("/home/bob_ross/the old mill.png");
+        assert!(conf_node.matches(&path));
+        assert!(Arc::ptr_eq(
+            &conf_node.matchers(),
+            &conf_node.clone().matchers()
+        ));
+        let conf_node = Node {
+            path_pattern: Some(String::from("*.txt")),
+            ..conf_node
+        };
+        assert!(!conf_node.matches(&path));
+        assert_eq!(conf_node, conf_node.clone());
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_conf_node_explain() {
+        let conf_node = Node {
+            root: PathBuf::from("/home/bob_ross"),
+            path_pattern: Some(String::from("**/*.png")),
+            ignore: vec![String::from("drafts")],
+            ..Default::default()
+        };
+        let cases = [
+            (
+                "/home/bob_ross/happy/the old mill.png",
+                Verdict::Matches {
+                    pattern: String::from("**/*.png"),
+                    rel: PathBuf::from("happy/the old mill.png"),
+                },
+            ),
+            (
+                "/home/bob_ross/drafts/cabin/mountain.png",
+                Verdict::Ignored {
+                    pattern: String::from("drafts"),
+                    ignored: PathBuf::from("drafts"),
+                },
+            ),
+            (
+                "/home/bob_ross/notes.txt",
+                Verdict::NoMatch {
+                    pattern: String::from("**/*.png"),
+                    rel: PathBuf::from("notes.txt"),
+                },
+            ),
+            (
+                "/home/steve_ross/the old mill.png",
+                Verdict::OutsideRoot {
+                    root: PathBuf::from("/home/bob_ross"),
+                },
+            ),
+        ];
+        for (path, verdict) in cases {
+            let path = Path::new(path);
+            assert_eq!(conf_node.explain(path), verdict);
+            assert_eq!(verdict.is_match(), conf_node.matches(path));
+        }
+        assert_eq!(
+            Verdict::Ignored {
+                pattern: String::from("drafts"),
+                ignored: PathBuf::from("drafts"),
+            }
+            .to_string(),
+            "no match, drafts is ignored by drafts"
+        );
+
+        let conf_node = Node {
+            path_pattern: None,
+            ..conf_node
+        };
+        assert_eq!(
+            conf_node.explain(Path::new("/home/bob_ross")),
+            Verdict::Root
+        );
+        assert!(!conf_node
+            .explain(Path::new("/home/bob_ross/notes.txt"))
+            .is_match());
+    }
 }
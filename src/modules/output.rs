@@ -0,0 +1,240 @@
+use std::{
+    hash::{Hash, Hasher},
+    io::{self, BufRead, BufReader, IsTerminal, Read, Write},
+    sync::OnceLock,
+    time::Duration,
+};
+use stderrlog::ColorChoice;
+#[cfg(feature = "timestamps")]
+use stderrlog::Timestamp;
+use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
+
+use super::events;
+
+/// Colors cycled through for node prefixes.
+const PALETTE: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Red,
+];
+
+static STYLE: OnceLock<Style> = OnceLock::new();
+
+/// How command output is prefixed, set up alongside the logger so both agree
+/// on colors and timestamps.
+#[derive(Clone, Copy, Debug)]
+pub struct Style {
+    pub color: ColorChoice,
+    #[cfg(feature = "timestamps")]
+    pub timestamp: Timestamp,
+    /// Prefixes are padded to this many characters so output lines up.
+    pub width: usize,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        return Self {
+            color: ColorChoice::Auto,
+            #[cfg(feature = "timestamps")]
+            timestamp: Timestamp::Off,
+            width: 0,
+        };
+    }
+}
+
+/// Sets the style for every command's output, only the first call counts.
+pub fn init(style: Style) {
+    let _ = STYLE.set(style);
+}
+
+fn style() -> Style {
+    STYLE.get().copied().unwrap_or_default()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn open(&self, color: ColorChoice) -> StandardStream {
+        let is_terminal = match self {
+            Self::Stdout => io::stdout().is_terminal(),
+            Self::Stderr => io::stderr().is_terminal(),
+        };
+        let color = match color {
+            ColorChoice::Auto if !is_terminal => ColorChoice::Never,
+            color => color,
+        };
+        match self {
+            Self::Stdout => StandardStream::stdout(color),
+            Self::Stderr => StandardStream::stderr(color),
+        }
+    }
+}
+
+/// The color `label` is always shown in.
+fn color(label: &str) -> Color {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    label.hash(&mut hasher);
+    PALETTE[(hasher.finish() % PALETTE.len() as u64) as usize]
+}
+
+#[cfg(feature = "timestamps")]
+fn write_timestamp(writer: &mut impl io::Write, timestamp: Timestamp) -> io::Result<()> {
+    let format = match timestamp {
+        Timestamp::Second => "%Y-%m-%dT%H:%M:%S%:z",
+        Timestamp::Millisecond => "%Y-%m-%dT%H:%M:%S%.3f%:z",
+        Timestamp::Microsecond => "%Y-%m-%dT%H:%M:%S%.6f%:z",
+        Timestamp::Nanosecond => "%Y-%m-%dT%H:%M:%S%.9f%:z",
+        Timestamp::Off => return Ok(()),
+    };
+    write!(writer, "{} ", chrono::Local::now().format(format))
+}
+
+fn write_prefix(writer: &mut impl WriteColor, style: &Style, label: &str) -> io::Result<()> {
+    #[cfg(feature = "timestamps")]
+    write_timestamp(writer, style.timestamp)?;
+    writer.set_color(ColorSpec::new().set_fg(Some(color(label))).set_bold(true))?;
+    write!(writer, "[{label}]")?;
+    writer.reset()?;
+    let padding = style.width.saturating_sub(label.chars().count());
+    write!(writer, "{:padding$} ", "")
+}
+
+/// Writes a line of a node's output behind its `[label]` prefix.
+pub fn write_line(
+    writer: &mut impl WriteColor,
+    style: &Style,
+    label: &str,
+    line: &[u8],
+) -> io::Result<()> {
+    write_prefix(writer, style, label)?;
+    writer.write_all(line.strip_suffix(b"\n").unwrap_or(line))?;
+    writeln!(writer)
+}
+
+/// Writes whether a run of a node succeeded and how long it took.
+pub fn write_status(
+    writer: &mut impl WriteColor,
+    style: &Style,
+    label: &str,
+    succeeded: bool,
+    duration: Duration,
+) -> io::Result<()> {
+    write_prefix(writer, style, label)?;
+    let (color, outcome) = match succeeded {
+        true => (Color::Green, "Succeeded"),
+        false => (Color::Red, "Failed"),
+    };
+    writer.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+    write!(writer, "{outcome}")?;
+    writer.reset()?;
+    writeln!(writer, " in {duration:.2?}")
+}
+
+/// Prints the status line of a run on stderr, ringing the bell if asked to.
+pub fn status(label: &str, succeeded: bool, duration: Duration, bell: bool) {
+    let style = style();
+    let out = Stream::Stderr.open(style.color);
+    let mut out = out.lock();
+    let _ = write_status(&mut out, &style, label, succeeded, duration);
+    if bell {
+        let _ = out.write_all(b"\x07");
+    }
+    let _ = out.flush();
+}
+
+/// Clears the terminal and its scrollback, if stdout is one.
+pub fn clear_screen() {
+    let mut out = io::stdout().lock();
+    if out.is_terminal() {
+        let _ = out.write_all(b"\x1b[2J\x1b[3J\x1b[H");
+        let _ = out.flush();
+    }
+}
+
+/// Copies `reader` to `stream` line by line, prefixing every line with the
+/// node's label. Lines of concurrent commands never interleave.
+pub fn forward(label: &str, stream: Stream, reader: impl Read) {
+    let style = style();
+    let stream = match events::is_enabled() {
+        true => Stream::Stderr,
+        false => stream,
+    };
+    let out = stream.open(style.color);
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {
+                let _ = write_line(&mut out.lock(), &style, label, &line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use termcolor::Buffer;
+
+    use super::*;
+
+    #[test]
+    fn test_output_write_line() {
+        let style = Style {
+            width: 6,
+            ..Default::default()
+        };
+        let mut buffer = Buffer::no_color();
+        write_line(&mut buffer, &style, "api", b"listening on :8080\n").unwrap();
+        write_line(&mut buffer, &style, "worker", b"ready").unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(buffer.as_slice()),
+            "[api]    listening on :8080\n[worker] ready\n"
+        );
+
+        let mut buffer = Buffer::ansi();
+        write_line(&mut buffer, &style, "api", b"ok\n").unwrap();
+        let output = String::from_utf8_lossy(buffer.as_slice()).to_string();
+        assert!(output.starts_with("\x1b[0m\x1b[1m\x1b["));
+        assert!(output.ends_with("[api]\x1b[0m    ok\n"));
+        assert_eq!(color("api"), color("api"));
+    }
+
+    #[test]
+    fn test_output_write_status() {
+        let style = Style {
+            width: 6,
+            ..Default::default()
+        };
+        let mut buffer = Buffer::no_color();
+        write_status(
+            &mut buffer,
+            &style,
+            "api",
+            true,
+            Duration::from_millis(1250),
+        )
+        .unwrap();
+        write_status(
+            &mut buffer,
+            &style,
+            "worker",
+            false,
+            Duration::from_millis(40),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(buffer.as_slice()),
+            "[api]    Succeeded in 1.25s\n[worker] Failed in 40.00ms\n"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use log::{error, info, warn};
+use std::time::{Duration, Instant};
+
+use super::{
+    command::{expand, Cancel, Change, Job},
+    node::{Node, OnFailure},
+    output,
+};
+
+/// Runs a node's pipeline on changes, holding on to its long running step
+/// between runs.
+#[derive(Debug)]
+pub struct Runner {
+    conf_node: Box<Node>,
+    service: Option<Job>,
+    /// Set once a failure made the node stop watching.
+    disabled: bool,
+    /// Stops the current run, leaving the rest of its pipeline and retries.
+    cancel: Cancel,
+}
+
+impl Runner {
+    pub fn new(conf_node: Box<Node>) -> Self {
+        return Self {
+            conf_node,
+            service: None,
+            disabled: false,
+            cancel: Cancel::default(),
+        };
+    }
+
+    pub fn conf_node(&self) -> &Node {
+        &self.conf_node
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Cancels the run in progress from another thread, along with any run
+    /// after it.
+    pub fn cancel(&self) -> Cancel {
+        self.cancel.clone()
+    }
+
+    /// Whether the long running step started by the last run is still up.
+    pub fn is_running(&mut self) -> bool {
+        self.service
+            .as_mut()
+            .is_some_and(|service| service.is_running())
+    }
+
+    /// Stops the long running step of the last run, then runs the pipeline
+    /// for `changes` and reports how it went on a status line.
+    pub fn trigger(&mut self, changes: &[Change]) -> Result<(), ()> {
+        self.stop();
+        if self.conf_node.clear_screen {
+            output::clear_screen();
+        }
+        let started = Instant::now();
+        let result = self.run(changes);
+        output::status(
+            &self.conf_node.label(),
+            result.is_ok(),
+            started.elapsed(),
+            self.conf_node.bell,
+        );
+        return result;
+    }
+
+    /// Runs the pipeline, handling a failure as the node's `on_failure` says.
+    fn run(&mut self, changes: &[Change]) -> Result<(), ()> {
+        let label = self.conf_node.label();
+        let mut attempt = 0;
+        while self.run_pipeline(changes).is_err() {
+            if self.cancel.is_cancelled() {
+                return Err(());
+            }
+            match &self.conf_node.on_failure {
+                OnFailure::Retry { attempts, backoff } if attempt < *attempts => {
+                    let delay = Duration::from_millis(backoff.saturating_mul(1 << attempt.min(16)));
+                    attempt += 1;
+                    warn!("Retrying {label} in {delay:?}, Attempt {attempt} of {attempts}");
+                    if !self.cancel.sleep(delay) {
+                        return Err(());
+                    }
+                    continue;
+                }
+                OnFailure::StopWatching => {
+                    error!("Stopped Watching {label} after it Failed");
+                    self.disabled = true;
+                }
+                OnFailure::Run(command) => {
+                    let command = expand(command, &self.conf_node, changes);
+                    let timeout = self.conf_node.timeout.map(Duration::from_secs);
+                    let _ = Job::start(&command, &self.conf_node, changes)
+                        .and_then(|job| job.wait(timeout, &self.cancel));
+                }
+                OnFailure::Ignore | OnFailure::Retry { .. } => (),
+            }
+            return Err(());
+        }
+        return Ok(());
+    }
+
+    /// Runs every step for every batch, stopping at the first that fails.
+    fn run_pipeline(&mut self, changes: &[Change]) -> Result<(), ()> {
+        let conf_node = &self.conf_node;
+        let pipeline = conf_node.pipeline();
+        for batch in conf_node.batches(changes) {
+            for (index, step) in pipeline.iter().enumerate() {
+                if self.cancel.is_cancelled() {
+                    return Err(());
+                }
+                let command = expand(&step.exec, conf_node, batch);
+                let job = Job::start(&command, conf_node, batch)?;
+                if step.long_running {
+                    self.service = Some(job);
+                    return Ok(());
+                }
+                let succeeded = job
+                    .wait(conf_node.step_timeout(step), &self.cancel)
+                    .is_ok_and(|status| conf_node.is_success(&status));
+                if !succeeded {
+                    if index + 1 < pipeline.len() {
+                        error!(
+                            "Skipping {} Remaining Steps of {}",
+                            pipeline.len() - index - 1,
+                            conf_node.label()
+                        );
+                    }
+                    return Err(());
+                }
+            }
+        }
+        info!("Finished {}", conf_node.label());
+        return Ok(());
+    }
+
+    /// Hands over the long running step of the last run, if it has one.
+    pub fn take_service(&mut self) -> Option<Job> {
+        self.service.take()
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(mut service) = self.service.take() {
+            service.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use std::{env::temp_dir, fs, path::PathBuf, time::Instant};
+
+    use super::*;
+    use crate::modules::{command::EventKind, node::Step};
+
+    fn step(exec: String) -> Step {
+        Step {
+            exec,
+            ..Default::default()
+        }
+    }
+
+    fn changes() -> Vec<Change> {
+        vec![Change {
+            path: PathBuf::from("/home/bob_ross/the old mill.png"),
+            kind: EventKind::Modified,
+        }]
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_runner_stop_on_failure() {
+        let out = temp_dir().join("runner_test1.txt");
+        let _ = fs::remove_file(&out);
+        let mut runner = Runner::new(Box::from(Node {
+            root: temp_dir(),
+            steps: vec![
+                step(format!("echo build >> {}", out.display())),
+                step(String::from("false")),
+                step(format!("echo test >> {}", out.display())),
+            ],
+            ..Default::default()
+        }));
+        assert_eq!(runner.trigger(&changes()), Err(()));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "build\n");
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_runner_step_timeout() {
+        let mut runner = Runner::new(Box::from(Node {
+            root: temp_dir(),
+            steps: vec![Step {
+                exec: String::from("sleep 10"),
+                timeout: Some(1),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }));
+        let start = Instant::now();
+        assert_eq!(runner.trigger(&changes()), Err(()));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_runner_restart_long_running() {
+        let out = temp_dir().join("runner_test3.txt");
+        let _ = fs::remove_file(&out);
+        let mut runner = Runner::new(Box::from(Node {
+            root: temp_dir(),
+            steps: vec![
+                step(format!("echo build >> {}", out.display())),
+                Step {
+                    exec: String::from("sleep 30"),
+                    long_running: true,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }));
+        let start = Instant::now();
+        assert_eq!(runner.trigger(&changes()), Ok(()));
+        assert!(runner.is_running());
+        assert_eq!(runner.trigger(&changes()), Ok(()));
+        assert!(runner.is_running());
+        runner.stop();
+        assert!(!runner.is_running());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "build\nbuild\n");
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_runner_success_codes() {
+        let mut runner = Runner::new(Box::from(Node {
+            root: temp_dir(),
+            exec: Some(String::from("exit 3")),
+            success_codes: vec![0, 3],
+            ..Default::default()
+        }));
+        assert_eq!(runner.trigger(&changes()), Ok(()));
+        let mut runner = Runner::new(Box::from(Node {
+            success_codes: vec![1],
+            exec: Some(String::from("true")),
+            ..runner.conf_node().clone()
+        }));
+        assert_eq!(runner.trigger(&changes()), Err(()));
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_runner_on_failure() {
+        let out = temp_dir().join("runner_test5.txt");
+        let _ = fs::remove_file(&out);
+        let conf_node = Node {
+            root: temp_dir(),
+            exec: Some(format!("echo run >> {}; false", out.display())),
+            on_failure: OnFailure::Retry {
+                attempts: 2,
+                backoff: 10,
+            },
+            ..Default::default()
+        };
+        let mut runner = Runner::new(Box::from(conf_node.clone()));
+        assert_eq!(runner.trigger(&changes()), Err(()));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "run\nrun\nrun\n");
+        assert!(!runner.is_disabled());
+
+        let _ = fs::remove_file(&out);
+        let mut runner = Runner::new(Box::from(Node {
+            on_failure: OnFailure::Run(format!("echo {{event}} failed >> {}", out.display())),
+            ..conf_node.clone()
+        }));
+        assert_eq!(runner.trigger(&changes()), Err(()));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "run\nmodified failed\n");
+
+        let mut runner = Runner::new(Box::from(Node {
+            on_failure: OnFailure::StopWatching,
+            ..conf_node
+        }));
+        assert_eq!(runner.trigger(&changes()), Err(()));
+        assert!(runner.is_disabled());
+        let _ = fs::remove_file(out);
+    }
+}
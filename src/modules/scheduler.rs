@@ -0,0 +1,598 @@
+use log::{info, warn};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, available_parallelism, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    command::{Cancel, Change, EventKind},
+    config::Config,
+    graph::Graph,
+    node::Node,
+    runner::Runner,
+    signals,
+};
+
+/// How often waiting checks whether irminsul got a signal to stop.
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A run of a node waiting for a worker.
+#[derive(Debug)]
+struct Queued {
+    index: usize,
+    changes: Vec<Change>,
+    /// When the run was first queued, earlier runs go first among equals.
+    seq: usize,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Taken out by the worker running the node.
+    runners: Vec<Option<Runner>>,
+    queue: Vec<Queued>,
+    running: BTreeSet<usize>,
+    results: Vec<Option<Result<(), ()>>>,
+    /// When the last run of each node started and, once it did, finished.
+    windows: Vec<Option<(SystemTime, Option<SystemTime>)>>,
+    /// Nodes that stopped watching after failing.
+    disabled: BTreeSet<usize>,
+    seq: usize,
+    shutdown: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    graph: Graph,
+    nodes: Vec<Box<Node>>,
+    /// Cancels the runs of each node, even while a worker has its runner.
+    cancels: Vec<Cancel>,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// Runs triggered nodes on a pool of `max_parallel` workers, higher priority
+/// nodes first, each only once the nodes it depends on are done.
+///
+/// A node runs one batch at a time. Changes for a node that is already queued
+/// are merged into the queued run instead of queueing another one.
+#[derive(Debug)]
+pub struct Scheduler {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    pub fn new(config: &Config) -> Result<Self, ()> {
+        let graph = Graph::new(&config.nodes)?;
+        let workers = match config.max_parallel {
+            0 => available_parallelism().map(usize::from).unwrap_or(1),
+            max_parallel => max_parallel,
+        }
+        .min(config.nodes.len())
+        .max(1);
+        let runners = config
+            .nodes
+            .iter()
+            .cloned()
+            .map(Runner::new)
+            .collect::<Vec<_>>();
+        let shared = Arc::new(Shared {
+            graph,
+            nodes: config.nodes.clone(),
+            cancels: runners.iter().map(Runner::cancel).collect(),
+            state: Mutex::new(State {
+                runners: runners.into_iter().map(Some).collect(),
+                queue: Vec::new(),
+                running: BTreeSet::new(),
+                results: vec![None; config.nodes.len()],
+                windows: vec![None; config.nodes.len()],
+                disabled: BTreeSet::new(),
+                seq: 0,
+                shutdown: false,
+            }),
+            changed: Condvar::new(),
+        });
+        let workers = (0..workers)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || shared.work())
+            })
+            .collect();
+        return Ok(Self { shared, workers });
+    }
+
+    /// Queues a run of the node at `index`, or adds `changes` to the run
+    /// already queued for it.
+    pub fn schedule(&self, index: usize, changes: Vec<Change>) {
+        self.schedule_all(BTreeMap::from([(index, changes)]));
+    }
+
+    /// Queues the runs for every node triggered by the same poll at once, so
+    /// none of them starts ahead of a dependency queued alongside it.
+    pub fn schedule_all(&self, triggered: BTreeMap<usize, Vec<Change>>) {
+        let mut state = self.shared.lock();
+        for (index, changes) in triggered {
+            state.schedule(&self.shared, index, changes);
+        }
+        drop(state);
+        self.shared.changed.notify_all();
+    }
+
+    /// How the last run of the node at `index` went, if it ran yet.
+    pub fn result(&self, index: usize) -> Option<Result<(), ()>> {
+        self.shared.lock().results[index]
+    }
+
+    /// Whether the node at `index` is queued or running.
+    pub fn is_busy(&self, index: usize) -> bool {
+        let state = self.shared.lock();
+        return state.running.contains(&index)
+            || state.queue.iter().any(|queued| queued.index == index);
+    }
+
+    /// Whether a worker is running the node at `index`.
+    pub fn is_running(&self, index: usize) -> bool {
+        self.shared.lock().running.contains(&index)
+    }
+
+    /// Whether the node at `index` stopped watching after a failure.
+    pub fn is_disabled(&self, index: usize) -> bool {
+        self.shared.lock().disabled.contains(&index)
+    }
+
+    /// Whether the last run of the node at `index` was going on at `time`.
+    pub fn ran_at(&self, index: usize, time: SystemTime) -> bool {
+        return match self.shared.lock().windows[index] {
+            Some((started, finished)) => {
+                started <= time && finished.is_none_or(|finished| time <= finished)
+            }
+            None => false,
+        };
+    }
+
+    /// Whether any node is still watching, ie. has not stopped after a
+    /// failure.
+    pub fn is_watching(&self) -> bool {
+        self.shared.lock().disabled.len() < self.shared.nodes.len()
+    }
+
+    /// Blocks until nothing is queued or running, or irminsul got a signal
+    /// to stop.
+    pub fn wait(&self) {
+        let mut state = self.shared.lock();
+        while (!state.queue.is_empty() || !state.running.is_empty())
+            && signals::received().is_none()
+        {
+            state = self
+                .shared
+                .changed
+                .wait_timeout(state, SIGNAL_INTERVAL)
+                .expect("Scheduler Lock Poisoned")
+                .0;
+        }
+    }
+
+    /// Waits for the long running steps started so far to exit by themselves.
+    pub fn wait_services(&self) {
+        let services = self
+            .shared
+            .lock()
+            .runners
+            .iter_mut()
+            .flatten()
+            .filter_map(|runner| Some((runner.take_service()?, runner.cancel())))
+            .collect::<Vec<_>>();
+        for (service, cancel) in services {
+            let _ = service.wait(None, &cancel);
+        }
+    }
+
+    /// Drops queued runs and stops running ones along with long running steps.
+    pub fn stop(&mut self) {
+        self.shared.cancels.iter().for_each(Cancel::cancel);
+        {
+            let mut state = self.shared.lock();
+            state.shutdown = true;
+            state.queue.clear();
+        }
+        self.shared.changed.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        // Long running steps get their grace period at the same time rather
+        // than one after the other.
+        let mut state = self.shared.lock();
+        thread::scope(|scope| {
+            for runner in state.runners.iter_mut().flatten() {
+                scope.spawn(|| runner.stop());
+            }
+        });
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("Scheduler Lock Poisoned")
+    }
+
+    fn work(&self) {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return;
+            }
+            let Some(queued) = state.next(self) else {
+                state = self.changed.wait(state).expect("Scheduler Lock Poisoned");
+                continue;
+            };
+            let mut runner = state.runners[queued.index]
+                .take()
+                .expect("Runner is Already Running");
+            state.running.insert(queued.index);
+            state.windows[queued.index] = Some((SystemTime::now(), None));
+            drop(state);
+
+            let result = runner.trigger(&queued.changes);
+
+            state = self.lock();
+            if runner.is_disabled() {
+                state.disabled.insert(queued.index);
+                state.queue.retain(|other| other.index != queued.index);
+            }
+            state.runners[queued.index] = Some(runner);
+            state.running.remove(&queued.index);
+            if let Some((_, finished)) = &mut state.windows[queued.index] {
+                *finished = Some(SystemTime::now());
+            }
+            state.finish(self, queued.index, result);
+            self.changed.notify_all();
+        }
+    }
+}
+
+impl State {
+    fn schedule(&mut self, shared: &Shared, index: usize, changes: Vec<Change>) {
+        if self.disabled.contains(&index) {
+            return;
+        }
+        match self.queue.iter_mut().find(|queued| queued.index == index) {
+            Some(queued) => {
+                info!("Superseding Queued Run of {}", shared.nodes[index].label());
+                merge_changes(&mut queued.changes, changes);
+            }
+            None => {
+                self.queue.push(Queued {
+                    index,
+                    changes,
+                    seq: self.seq,
+                });
+                self.seq += 1;
+            }
+        }
+    }
+
+    /// Takes the first queued run that can start, if any.
+    fn next(&mut self, shared: &Shared) -> Option<Queued> {
+        let position = self
+            .queue
+            .iter()
+            .enumerate()
+            .filter(|(_, queued)| self.is_runnable(shared, queued.index))
+            .max_by_key(|(_, queued)| {
+                (
+                    shared.nodes[queued.index].priority,
+                    std::cmp::Reverse(queued.seq),
+                )
+            })
+            .map(|(position, _)| position)?;
+        return Some(self.queue.remove(position));
+    }
+
+    /// Whether the node is idle and none of its dependencies, direct or
+    /// further up, are pending. A pending dependency further up cascades to
+    /// the node once it is done, so the node waits for that instead of running
+    /// twice.
+    fn is_runnable(&self, shared: &Shared, index: usize) -> bool {
+        if self.running.contains(&index) {
+            return false;
+        }
+        let pending = |index: &usize| {
+            self.running.contains(index) || self.queue.iter().any(|queued| queued.index == *index)
+        };
+        return !shared.graph.ancestors(index).iter().any(pending);
+    }
+
+    /// Records the result, queueing dependents after a success and dropping
+    /// the queued ones after a failure.
+    fn finish(&mut self, shared: &Shared, index: usize, result: Result<(), ()>) {
+        self.results[index] = Some(result);
+        if self.shutdown {
+            return;
+        }
+        if result.is_ok() {
+            for dependent in shared.graph.dependents(index) {
+                self.schedule(shared, *dependent, Vec::new());
+            }
+            return;
+        }
+        let mut pending = shared.graph.dependents(index).to_vec();
+        while let Some(dependent) = pending.pop() {
+            let Some(position) = self
+                .queue
+                .iter()
+                .position(|queued| queued.index == dependent)
+            else {
+                continue;
+            };
+            self.queue.remove(position);
+            self.results[dependent] = Some(Err(()));
+            warn!(
+                "Skipping {} as {} Failed",
+                shared.nodes[dependent].label(),
+                shared.nodes[index].label()
+            );
+            pending.extend(shared.graph.dependents(dependent));
+        }
+    }
+}
+
+/// Folds newer changes into queued ones, keeping a single change per path.
+/// A path created and then modified before running is still reported as
+/// created.
+fn merge_changes(queued: &mut Vec<Change>, changes: Vec<Change>) {
+    for change in changes {
+        match queued.iter_mut().find(|queued| queued.path == change.path) {
+            Some(queued) => {
+                if queued.kind != EventKind::Created || change.kind != EventKind::Modified {
+                    queued.kind = change.kind;
+                }
+            }
+            None => queued.push(change),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+    use std::{
+        env::temp_dir,
+        fs,
+        path::PathBuf,
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::modules::node::OnFailure;
+
+    fn conf_node(name: &str, exec: String) -> Box<Node> {
+        Box::from(Node {
+            name: Some(name.to_owned()),
+            root: temp_dir(),
+            exec: Some(exec),
+            ..Default::default()
+        })
+    }
+
+    fn change(path: &str, kind: EventKind) -> Change {
+        Change {
+            path: PathBuf::from(path),
+            kind,
+        }
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_scheduler_priority_and_supersede() {
+        let out = temp_dir().join("scheduler_test1.txt");
+        let _ = fs::remove_file(&out);
+        let log = |name: &str| format!("echo {name} {{paths}} >> {}", out.display());
+        let config = Config {
+            max_parallel: 1,
+            nodes: vec![
+                Box::from(Node {
+                    priority: 10,
+                    ..*conf_node("blocker", format!("sleep 0.5; {}", log("blocker")))
+                }),
+                conf_node("low", log("low")),
+                Box::from(Node {
+                    priority: 5,
+                    ..*conf_node("high", log("high"))
+                }),
+            ],
+            ..Default::default()
+        };
+        let scheduler = Scheduler::new(&config).unwrap();
+        scheduler.schedule(0, Vec::new());
+        scheduler.schedule(1, vec![change("a.txt", EventKind::Created)]);
+        scheduler.schedule(2, Vec::new());
+        scheduler.schedule(1, vec![change("b.txt", EventKind::Modified)]);
+        scheduler.schedule(1, vec![change("a.txt", EventKind::Modified)]);
+        scheduler.wait();
+
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "blocker\nhigh\nlow a.txt b.txt\n"
+        );
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_scheduler_max_parallel() {
+        let out = temp_dir().join("scheduler_test2.txt");
+        let _ = fs::remove_file(&out);
+        let exec = |name: &str| {
+            format!(
+                "echo start >> {out}; sleep 0.3; echo {name} >> {out}",
+                out = out.display()
+            )
+        };
+        let config = Config {
+            max_parallel: 2,
+            nodes: vec![
+                conf_node("a", exec("a")),
+                conf_node("b", exec("b")),
+                conf_node("c", exec("c")),
+            ],
+            ..Default::default()
+        };
+        let scheduler = Scheduler::new(&config).unwrap();
+        (0..3).for_each(|index| scheduler.schedule(index, Vec::new()));
+        scheduler.wait();
+
+        let output = fs::read_to_string(&out).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(&lines[..2], &["start", "start"]);
+        assert_ne!(lines[2], "start");
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_scheduler_cascade() {
+        let out = temp_dir().join("scheduler_test3.txt");
+        let _ = fs::remove_file(&out);
+        let conf_node = |name: &str, exec: &str, depends_on: &[&str]| {
+            Box::from(Node {
+                depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+                ..*conf_node(name, format!("{exec} && echo {name} >> {}", out.display()))
+            })
+        };
+        let mut config = Config {
+            nodes: vec![
+                conf_node("server", "true", &["proto"]),
+                conf_node("web", "true", &["server"]),
+                conf_node("proto", "sleep 0.2", &[]),
+                conf_node("docs", "true", &[]),
+            ],
+            ..Default::default()
+        };
+        let scheduler = Scheduler::new(&config).unwrap();
+        scheduler.schedule_all(BTreeMap::from([(0, Vec::new()), (2, Vec::new())]));
+        scheduler.wait();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "proto\nserver\nweb\n");
+        assert_eq!(scheduler.result(1), Some(Ok(())));
+        assert_eq!(scheduler.result(3), None);
+
+        let _ = fs::remove_file(&out);
+        config.nodes[2] = conf_node("proto", "false", &[]);
+        let scheduler = Scheduler::new(&config).unwrap();
+        scheduler.schedule_all(BTreeMap::from([(0, Vec::new()), (2, Vec::new())]));
+        scheduler.wait();
+        assert_eq!(scheduler.result(0), Some(Err(())));
+        assert_eq!(scheduler.result(1), None);
+        assert!(!out.exists());
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_scheduler_transitive_dependency() {
+        let out = temp_dir().join("scheduler_test4.txt");
+        let _ = fs::remove_file(&out);
+        let conf_node = |name: &str, exec: &str, depends_on: &[&str]| {
+            Box::from(Node {
+                depends_on: depends_on.iter().map(|name| name.to_string()).collect(),
+                ..*conf_node(name, format!("{exec} && echo {name} >> {}", out.display()))
+            })
+        };
+        let config = Config {
+            max_parallel: 3,
+            nodes: vec![
+                conf_node("web", "true", &["server"]),
+                conf_node("server", "true", &["proto"]),
+                conf_node("proto", "sleep 0.3", &[]),
+            ],
+            ..Default::default()
+        };
+        let scheduler = Scheduler::new(&config).unwrap();
+        scheduler.schedule_all(BTreeMap::from([(0, Vec::new()), (2, Vec::new())]));
+        scheduler.wait();
+        assert_eq!(fs::read_to_string(&out).unwrap(), "proto\nserver\nweb\n");
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_scheduler_stop_watching() {
+        let config = Config {
+            nodes: vec![Box::from(Node {
+                on_failure: OnFailure::StopWatching,
+                ..*conf_node("failing", String::from("false"))
+            })],
+            ..Default::default()
+        };
+        let scheduler = Scheduler::new(&config).unwrap();
+        assert!(scheduler.is_watching());
+        scheduler.schedule(0, Vec::new());
+        scheduler.wait();
+        assert!(!scheduler.is_watching());
+        scheduler.schedule(0, Vec::new());
+        scheduler.wait();
+        assert_eq!(scheduler.result(0), Some(Err(())));
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_scheduler_stop() {
+        let out = temp_dir().join("scheduler_test5.txt");
+        let _ = fs::remove_file(&out);
+        let config = Config {
+            max_parallel: 1,
+            nodes: vec![
+                conf_node("slow", String::from("sleep 30")),
+                conf_node("queued", format!("echo queued >> {}", out.display())),
+            ],
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new(&config).unwrap();
+        scheduler.schedule(0, Vec::new());
+        scheduler.schedule(1, Vec::new());
+        sleep(Duration::from_millis(200));
+        scheduler.stop();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(scheduler.result(0), Some(Err(())));
+        assert!(!out.exists());
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_scheduler_stop_during_backoff() {
+        let config = Config {
+            nodes: vec![Box::from(Node {
+                on_failure: OnFailure::Retry {
+                    attempts: 3,
+                    backoff: 20000,
+                },
+                ..*conf_node("retrying", String::from("false"))
+            })],
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let mut scheduler = Scheduler::new(&config).unwrap();
+        scheduler.schedule(0, Vec::new());
+        sleep(Duration::from_millis(300));
+        scheduler.stop();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(scheduler.result(0), Some(Err(())));
+    }
+}
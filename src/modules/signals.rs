@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// The signal irminsul was asked to stop with, 0 until then.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+#[cfg(unix)]
+extern "C" fn handle(signal: libc::c_int) {
+    RECEIVED.store(signal, Ordering::SeqCst);
+}
+
+/// Catches SIGINT and SIGTERM, so commands can be stopped before irminsul
+/// exits instead of being left behind.
+pub fn install() {
+    #[cfg(unix)]
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only stores to an atomic, which is async
+        // signal safe.
+        unsafe {
+            libc::signal(
+                signal,
+                handle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+/// The signal irminsul is stopping on, if it got one.
+pub fn received() -> Option<i32> {
+    return match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    };
+}
+
+/// The exit code for stopping on `signal`, as shells report it.
+pub fn exit_code(signal: i32) -> u8 {
+    return 128u8.saturating_add(signal as u8);
+}
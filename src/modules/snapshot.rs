@@ -0,0 +1,238 @@
+use log::error;
+use normpath::PathExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    command::{Change, EventKind},
+    config::Config,
+    treemap::Treemap,
+};
+
+/// The files of a [`Treemap`] and when each was last modified, saved between
+/// sessions to find what changed while irminsul was not running.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Nanoseconds since the epoch the snapshot was taken at.
+    pub taken: u64,
+    /// Every file by its path, with its modification time in nanoseconds
+    /// since the epoch.
+    pub files: BTreeMap<PathBuf, u64>,
+}
+
+fn nanos(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    return u64::try_from(since_epoch.as_nanos()).unwrap_or(u64::MAX);
+}
+
+impl Snapshot {
+    /// Records the files of `map` that exist. Directories are left out since
+    /// adding an entry to one changes its modification time too, and so is
+    /// `state_file`, which changes whenever a snapshot is saved to it.
+    pub fn take(map: &Treemap, state_file: Option<&Path>) -> Self {
+        let state_file = state_file.map(|state_file| {
+            state_file
+                .normalize()
+                .map(|path| path.into_path_buf())
+                .unwrap_or_else(|_| state_file.to_path_buf())
+        });
+        let files = map
+            .ids()
+            .filter(|id| !map.is_dir(*id) && map.branches(*id).len() == 0)
+            .filter_map(|id| {
+                let path = map.full_path(id);
+                if state_file.as_ref() == Some(&path) {
+                    return None;
+                }
+                let modified = map.last_update(&path)?;
+                Some((path, nanos(modified)))
+            })
+            .collect();
+        return Self {
+            taken: nanos(SystemTime::now()),
+            files,
+        };
+    }
+
+    pub fn taken(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_nanos(self.taken)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ()> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| error!("Failed to Read Snapshot {}: {err}", path.display()))?;
+        return serde_json::from_str(&contents)
+            .map_err(|err| error!("Failed to Parse Snapshot {}: {err}", path.display()));
+    }
+
+    /// Writes the snapshot next to `path` first and moves it over, so a
+    /// snapshot cut short never replaces a whole one.
+    pub fn save(&self, path: &Path) -> Result<(), ()> {
+        let contents = serde_json::to_string(self)
+            .map_err(|err| error!("Failed to Serialize Snapshot: {err}"))?;
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, contents)
+            .and_then(|_| fs::rename(&partial, path))
+            .map_err(|err| error!("Failed to Save Snapshot {}: {err}", path.display()))
+    }
+
+    /// The files modified after `time`, as modifications since files removed
+    /// before then cannot be told apart from ones that never existed.
+    pub fn modified_since(&self, time: SystemTime) -> Vec<Change> {
+        let time = nanos(time);
+        return self
+            .files
+            .iter()
+            .filter(|(_, modified)| **modified > time)
+            .map(|(path, _)| Change {
+                path: path.clone(),
+                kind: EventKind::Modified,
+            })
+            .collect();
+    }
+
+    /// What changed between this snapshot and `current`, by path.
+    pub fn diff(&self, current: &Self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (path, modified) in &current.files {
+            let kind = match self.files.get(path) {
+                None => EventKind::Created,
+                Some(before) if before != modified => EventKind::Modified,
+                Some(_) => continue,
+            };
+            changes.push(Change {
+                path: path.clone(),
+                kind,
+            });
+        }
+        for path in self.files.keys() {
+            if !current.files.contains_key(path) {
+                changes.push(Change {
+                    path: path.clone(),
+                    kind: EventKind::Removed,
+                });
+            }
+        }
+        changes.sort();
+        return changes;
+    }
+}
+
+/// The nodes matching each change, by node index. Removed files are no longer
+/// in the tree, so changes are matched against the nodes' patterns instead,
+/// leaving out changes to a node's own outputs.
+pub fn route(config: &Config, changes: &[Change]) -> BTreeMap<usize, Vec<Change>> {
+    let mut triggered: BTreeMap<usize, Vec<Change>> = BTreeMap::new();
+    for change in changes {
+        for (index, conf_node) in config.nodes.iter().enumerate() {
+            if conf_node.matches(&change.path) && !conf_node.is_output(&change.path) {
+                triggered.entry(index).or_default().push(change.clone());
+            }
+        }
+    }
+    return triggered;
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use std::env::temp_dir;
+
+    use super::*;
+    use crate::modules::node::Node;
+
+    #[test]
+    fn test_snapshot_diff() {
+        let before = Snapshot {
+            taken: 0,
+            files: BTreeMap::from([
+                (PathBuf::from("/home/bob_ross/the old mill.png"), 1),
+                (PathBuf::from("/home/bob_ross/happy trees.png"), 1),
+                (PathBuf::from("/home/bob_ross/notes.txt"), 1),
+            ]),
+        };
+        let after = Snapshot {
+            taken: 2,
+            files: BTreeMap::from([
+                (PathBuf::from("/home/bob_ross/the old mill.png"), 1),
+                (PathBuf::from("/home/bob_ross/happy trees.png"), 2),
+                (PathBuf::from("/home/bob_ross/cabin.png"), 2),
+            ]),
+        };
+        let change = |path: &str, kind| Change {
+            path: PathBuf::from(path),
+            kind,
+        };
+        assert_eq!(
+            after.modified_since(UNIX_EPOCH + Duration::from_nanos(1)),
+            vec![
+                change("/home/bob_ross/cabin.png", EventKind::Modified),
+                change("/home/bob_ross/happy trees.png", EventKind::Modified),
+            ]
+        );
+        let changes = before.diff(&after);
+        assert_eq!(
+            changes,
+            vec![
+                change("/home/bob_ross/cabin.png", EventKind::Created),
+                change("/home/bob_ross/happy trees.png", EventKind::Modified),
+                change("/home/bob_ross/notes.txt", EventKind::Removed),
+            ]
+        );
+
+        let config = Config {
+            nodes: vec![
+                Box::from(Node {
+                    root: PathBuf::from("/home/bob_ross"),
+                    path_pattern: Some(String::from("*.png")),
+                    ..Default::default()
+                }),
+                Box::from(Node {
+                    root: PathBuf::from("/home/bob_ross"),
+                    path_pattern: Some(String::from("*.txt")),
+                    ..Default::default()
+                }),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            route(&config, &changes),
+            BTreeMap::from([(0, changes[..2].to_vec()), (1, changes[2..].to_vec())])
+        );
+    }
+
+    #[test]
+    fn test_snapshot_save_load() {
+        let dir = temp_dir().join("snapshot_test1");
+        let _ = fs::create_dir_all(&dir);
+        let file = dir.join("the old mill.png");
+        let path = dir.join("state.json");
+        fs::write(&file, "").unwrap();
+        fs::write(&path, "").unwrap();
+        let map = Node {
+            root: dir.clone(),
+            path_pattern: Some(String::from("*")),
+            ..Default::default()
+        }
+        .build_treemap();
+        let snapshot = Snapshot::take(&map, Some(&path));
+        assert_eq!(
+            snapshot.files.keys().collect::<Vec<_>>(),
+            vec![&map.full_path(map.find(&file).unwrap())]
+        );
+        snapshot.save(&path).unwrap();
+        assert_eq!(Snapshot::load(&path), Ok(snapshot.clone()));
+        assert_eq!(
+            snapshot.diff(&Snapshot::take(&map, Some(&path))),
+            Vec::new()
+        );
+        assert!(Snapshot::load(&dir.join("missing.json")).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -1,153 +1,940 @@
-use crate::utils::get_last_modified::get_last_modified;
 use log::trace;
-use rayon::iter::*;
-use std::{collections::BTreeSet, fmt::Display, hash::Hash, path::PathBuf, time::SystemTime};
+use rayon::prelude::*;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ffi::OsStr,
+    fmt::{Display, Write as _},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
-use super::node::Node;
+use super::{command::EventKind, node::Node};
 
-#[derive(Clone, Debug, PartialOrd, Eq, Ord, Hash)]
+/// Index of a point in a [`Treemap`]'s arena.
+pub type PointId = usize;
+
+/// The point every path in a [`Treemap`] starts from.
+pub const ROOT: PointId = 0;
+
+type SegmentId = u32;
+
+/// Path segments are stored once per tree; `mod.rs`, `src` and the like
+/// repeat across a monorepo far more often than they differ.
+#[derive(Clone, Debug, Default)]
+struct Segments {
+    names: Vec<Arc<OsStr>>,
+    ids: HashMap<Arc<OsStr>, SegmentId>,
+}
+
+impl Segments {
+    fn intern(&mut self, name: &OsStr) -> SegmentId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = self.names.len() as SegmentId;
+        let name: Arc<OsStr> = Arc::from(name);
+        self.names.push(name.clone());
+        self.ids.insert(name, id);
+        id
+    }
+
+    fn get(&self, name: &OsStr) -> Option<SegmentId> {
+        self.ids.get(name).copied()
+    }
+
+    fn name(&self, id: SegmentId) -> &OsStr {
+        &self.names[id as usize]
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Point {
+    segment: SegmentId,
+    parent: Option<PointId>,
+    /// Sorted by segment id for lookups, not by name.
+    branches: Vec<(SegmentId, PointId)>,
+    is_dir: bool,
+    last_update: Option<SystemTime>,
+    /// The config nodes owning this point, as indices into [`Treemap::nodes`].
+    conf_node: Vec<usize>,
+    /// The config nodes rooted at this point, as indices into
+    /// [`Treemap::nodes`]. Directories at and below it are rescanned with
+    /// their patterns when their mtime changes.
+    scope: Vec<usize>,
+}
+
+impl Point {
+    fn new(segment: SegmentId, parent: Option<PointId>) -> Self {
+        Self {
+            segment,
+            parent,
+            branches: Vec::new(),
+            is_dir: false,
+            last_update: None,
+            conf_node: Vec::new(),
+            scope: Vec::new(),
+        }
+    }
+}
+
+/// A tree of watched paths, one point per path segment.
+///
+/// Points live in a slab indexed by [`PointId`] and only store their interned
+/// segment and parent, so full paths are rebuilt on demand instead of being
+/// kept for every point.
+#[derive(Clone, Debug)]
 pub struct Treemap {
-    pub full_path: PathBuf,
-    pub node: PathBuf,
-    pub branches: Vec<Box<Treemap>>,
-    last_update: Option<Box<SystemTime>>,
-    conf_node: Vec<Box<Node>>,
+    /// The path the root segment is joined onto.
+    base: PathBuf,
+    points: Vec<Option<Point>>,
+    free: Vec<PointId>,
+    segments: Segments,
+    /// The config nodes in config order, so their indices here are the ones
+    /// in the config. Identical nodes are kept apart.
+    nodes: Vec<Box<Node>>,
+    /// Points added by the last structure pass, reported as created.
+    created: BTreeSet<PointId>,
 }
 
 impl PartialEq for Treemap {
     fn eq(&self, other: &Self) -> bool {
-        if self.full_path != other.full_path {
+        if self.base != other.base {
             return false;
         }
+        return self.eq_point(ROOT, other, ROOT);
+    }
+}
 
-        if self.node != other.node {
-            return false;
+impl Eq for Treemap {}
+
+impl Display for Treemap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(&BTreeSet::new()))
+    }
+}
+
+impl Treemap {
+    pub fn new(node: PathBuf, branches: Vec<Treemap>, prev_path: PathBuf) -> Self {
+        let mut val = Self {
+            base: prev_path,
+            points: Vec::new(),
+            free: Vec::new(),
+            segments: Segments::default(),
+            nodes: Vec::new(),
+            created: BTreeSet::new(),
+        };
+        let segment = val.segments.intern(node.as_os_str());
+        val.alloc(Point::new(segment, None));
+        val.init_points(&[ROOT]);
+        for branch in branches {
+            let offset = val.nodes.len();
+            val.nodes.extend(branch.nodes.iter().cloned());
+            val.graft(ROOT, &branch, ROOT, offset);
         }
+        return val;
+    }
 
-        if self.last_update != other.last_update {
-            return false;
+    /// Builds one tree shared by every node, with each path a node owns
+    /// tagged with it, so paths watched by several nodes are stat-ed once.
+    pub fn from_nodes(nodes: &[Box<Node>]) -> Self {
+        let owned = nodes
+            .par_iter()
+            .map(|node| node.build_paths())
+            .collect::<Vec<_>>();
+        let dirs = nodes.par_iter().flat_map(|node| node.build_dirs());
+        let paths = owned
+            .par_iter()
+            .flatten()
+            .cloned()
+            .chain(dirs)
+            .collect::<Vec<_>>();
+        let mut map = Self::from_paths(&paths);
+        for (node, paths) in nodes.iter().zip(owned) {
+            let index = map.add_conf_node(node.clone());
+            for path in paths {
+                if let Some(id) = map.find(&path) {
+                    map.link_conf_node(id, index);
+                }
+            }
+            if let Some(root) = map.find(&node.normalized_root()) {
+                map.set_scope(root, index);
+            }
+        }
+        return map;
+    }
+
+    /// Builds the tree for `paths` in one pass over them once sorted. Every
+    /// path must be absolute and on the same drive.
+    pub fn from_paths(paths: &[PathBuf]) -> Self {
+        let root = match cfg!(windows) {
+            true => OsStr::new("WinRoot"),
+            false => OsStr::new("/"),
+        };
+        let mut segments = paths
+            .par_iter()
+            .map(|path| {
+                let mut segments = Vec::new();
+                if cfg!(windows) {
+                    segments.push(root);
+                }
+                segments.extend(path.iter());
+                segments
+            })
+            .collect::<Vec<_>>();
+        segments.par_sort_unstable();
+        segments.dedup();
+        if segments.is_empty() {
+            segments.push(vec![root]);
         }
-        {
-            let a: BTreeSet<_> = self.branches.par_iter().collect();
-            let b: BTreeSet<_> = other.branches.par_iter().collect();
 
-            if a != b {
-                return false;
+        let mut val = Self::new(segments[0][0].into(), Vec::new(), PathBuf::new());
+        let mut chain = vec![ROOT];
+        for (index, path) in segments.iter().enumerate() {
+            // Sorted paths share their prefix with the previous one, so only
+            // the tail past that prefix needs new points.
+            let shared = match index {
+                0 => 1,
+                _ => path
+                    .iter()
+                    .zip(segments[index - 1].iter())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+                    .max(1),
+            };
+            chain.truncate(shared);
+            for segment in &path[shared..] {
+                let parent = *chain.last().unwrap();
+                let segment = val.segments.intern(segment);
+                let id = val.alloc(Point::new(segment, Some(parent)));
+                val.point_mut(parent).branches.push((segment, id));
+                chain.push(id);
             }
         }
+        val.points.iter_mut().flatten().for_each(|point| {
+            point.branches.sort_unstable();
+            point.branches.shrink_to_fit();
+        });
+        val.points.shrink_to_fit();
+        let ids = val.ids().collect::<Vec<_>>();
+        val.init_points(&ids);
+        return val;
+    }
 
-        {
-            let a: BTreeSet<_> = self.conf_node.par_iter().collect();
-            let b: BTreeSet<_> = other.conf_node.par_iter().collect();
+    fn point(&self, id: PointId) -> &Point {
+        self.points[id].as_ref().expect("Point was Removed")
+    }
 
-            if a != b {
-                return false;
+    fn point_mut(&mut self, id: PointId) -> &mut Point {
+        self.points[id].as_mut().expect("Point was Removed")
+    }
+
+    fn alloc(&mut self, point: Point) -> PointId {
+        match self.free.pop() {
+            Some(id) => {
+                self.points[id] = Some(point);
+                id
+            }
+            None => {
+                self.points.push(Some(point));
+                self.points.len() - 1
             }
         }
+    }
 
-        return true;
+    /// Every live point, in no particular order.
+    pub fn ids(&self) -> impl Iterator<Item = PointId> + '_ {
+        self.points
+            .iter()
+            .enumerate()
+            .filter_map(|(id, point)| point.as_ref().map(|_| id))
     }
-}
 
-impl Display for Treemap {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "-{} ", self.node.display())?;
-        for branch in &self.branches {
-            write!(f, "| ")?;
-            branch.fmt(f)?;
+    pub fn len(&self) -> usize {
+        self.points.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn node(&self, id: PointId) -> &OsStr {
+        self.segments.name(self.point(id).segment)
+    }
+
+    pub fn full_path(&self, id: PointId) -> PathBuf {
+        let mut chain = Vec::new();
+        let mut current = Some(id);
+        while let Some(id) = current {
+            let point = self.point(id);
+            chain.push(self.segments.name(point.segment));
+            current = point.parent;
         }
-        Ok(())
+        let mut full_path = self.base.clone();
+        for segment in chain.into_iter().rev() {
+            if !(cfg!(windows) && segment == "WinRoot") {
+                full_path.push(segment);
+            }
+        }
+        full_path
     }
-}
 
-impl Treemap {
-    pub fn new(node: PathBuf, branches: Vec<Box<Treemap>>, prev_path: PathBuf) -> Self {
-        let full_path;
-        if cfg!(windows) && node == PathBuf::from("WinRoot") {
-            full_path = prev_path;
-        } else {
-            full_path = prev_path.join(node.clone())
+    pub fn is_dir(&self, id: PointId) -> bool {
+        self.point(id).is_dir
+    }
+
+    pub fn branches(&self, id: PointId) -> impl ExactSizeIterator<Item = PointId> + '_ {
+        self.point(id).branches.iter().map(|(_, branch)| *branch)
+    }
+
+    pub fn branch(&self, id: PointId, name: &OsStr) -> Option<PointId> {
+        let segment = self.segments.get(name)?;
+        let branches = &self.point(id).branches;
+        let index = branches
+            .binary_search_by_key(&segment, |(segment, _)| *segment)
+            .ok()?;
+        Some(branches[index].1)
+    }
+
+    pub fn find(&self, path: &Path) -> Option<PointId> {
+        let rest = path.strip_prefix(self.full_path(ROOT)).ok()?;
+        rest.iter()
+            .try_fold(ROOT, |id, segment| self.branch(id, segment))
+    }
+
+    fn add_branch(&mut self, parent: PointId, name: &OsStr) -> PointId {
+        if let Some(id) = self.branch(parent, name) {
+            return id;
         }
-        let mut val = Self {
-            node: node,
-            branches,
-            last_update: None,
-            full_path: full_path,
-            conf_node: Vec::new(),
-        };
-        val.poll_point();
-        return val;
+        let segment = self.segments.intern(name);
+        let id = self.alloc(Point::new(segment, Some(parent)));
+        let branches = &mut self.point_mut(parent).branches;
+        let index = branches
+            .binary_search_by_key(&segment, |(segment, _)| *segment)
+            .unwrap_or_else(|index| index);
+        branches.insert(index, (segment, id));
+        self.init_points(&[id]);
+        id
     }
 
-    pub fn merge(&mut self, other: &mut Self) {
-        let mut branches = Vec::new();
-        '_outer: loop {
-            if self.branches.is_empty() {
-                break;
-            }
-            let mut branch = self.branches.pop().unwrap();
+    /// Adds the chain of points leading from the root down to `path`.
+    pub fn insert(&mut self, path: &Path) -> Option<PointId> {
+        let rest = path.strip_prefix(self.full_path(ROOT)).ok()?;
+        let mut id = ROOT;
+        for segment in rest.iter() {
+            id = self.add_branch(id, segment);
+        }
+        Some(id)
+    }
 
-            other.branches = other
+    /// Drops `id` and everything below it.
+    pub fn remove(&mut self, id: PointId) {
+        if let Some(parent) = self.point(id).parent {
+            self.point_mut(parent)
                 .branches
-                .iter_mut()
-                .filter_map(|b_branch| {
-                    if b_branch.full_path == branch.full_path {
-                        branch.merge(b_branch);
-                        None
-                    } else {
-                        Some(b_branch.to_owned())
-                    }
-                })
-                .collect::<Vec<_>>();
-            branches.push(branch);
+                .retain(|(_, branch)| *branch != id);
+        }
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if let Some(point) = self.points[id].take() {
+                pending.extend(point.branches.iter().map(|(_, branch)| *branch));
+                self.free.push(id);
+            }
         }
-        branches.append(&mut other.branches);
-        self.branches = branches;
     }
 
-    pub fn link_conf_node(&mut self, conf_node: Box<Node>) -> &mut Self {
-        self.conf_node.push(conf_node);
+    /// Copies `other_id` from `other` and everything below it into the branch
+    /// of `parent` with the same segment, creating it if needed. `other`'s
+    /// nodes are expected at `offset` onwards in this tree.
+    fn graft(&mut self, parent: PointId, other: &Treemap, other_id: PointId, offset: usize) {
+        let name = other.node(other_id);
+        let id = match self.branch(parent, name) {
+            Some(id) => id,
+            None => {
+                let segment = self.segments.intern(name);
+                let id = self.alloc(Point::new(segment, Some(parent)));
+                let source = other.point(other_id);
+                let point = self.point_mut(id);
+                point.is_dir = source.is_dir;
+                point.last_update = source.last_update;
+                let branches = &mut self.point_mut(parent).branches;
+                let index = branches
+                    .binary_search_by_key(&segment, |(segment, _)| *segment)
+                    .unwrap_or_else(|index| index);
+                branches.insert(index, (segment, id));
+                id
+            }
+        };
+        let source = other.point(other_id);
+        for scope in &source.scope {
+            self.set_scope(id, offset + scope);
+        }
+        for conf_node in &source.conf_node {
+            self.link_conf_node(id, offset + conf_node);
+        }
+        for branch in other.branches(other_id) {
+            self.graft(id, other, branch, offset);
+        }
+    }
+
+    /// Adds everything below `other`'s root to this tree, at the point with
+    /// the same full path as that root. `other`'s nodes are added after this
+    /// tree's.
+    pub fn merge(&mut self, other: Self) {
+        let Some(id) = self.insert(&other.full_path(ROOT)) else {
+            return;
+        };
+        let offset = self.nodes.len();
+        self.nodes.extend(other.nodes.iter().cloned());
+        for branch in other.branches(ROOT) {
+            self.graft(id, &other, branch, offset);
+        }
+    }
+
+    /// Adds a config node, returning the index points are linked to it by.
+    pub fn add_conf_node(&mut self, conf_node: Box<Node>) -> usize {
+        self.nodes.push(conf_node);
+        return self.nodes.len() - 1;
+    }
+
+    pub fn set_scope(&mut self, id: PointId, index: usize) -> &mut Self {
+        let point = self.point_mut(id);
+        if !point.scope.contains(&index) {
+            point.scope.push(index);
+        }
+        self
+    }
+
+    pub fn link_conf_node(&mut self, id: PointId, index: usize) -> &mut Self {
+        let point = self.point_mut(id);
+        if !point.conf_node.contains(&index) {
+            point.conf_node.push(index);
+        }
         self
     }
 
-    pub fn poll_point(&mut self) -> bool {
-        if self.full_path.exists() {
-            let t_time = Some(Box::new(
-                get_last_modified(self.full_path.clone()).expect("Failed to get Last Update Time"),
-            ));
-            if self.last_update.lt(&t_time) {
-                self.last_update = t_time;
+    /// The config nodes owning `path`, if it is in the tree.
+    pub fn owners(&self, path: &Path) -> Vec<&Node> {
+        match self.find(path) {
+            Some(id) => self
+                .point(id)
+                .conf_node
+                .iter()
+                .map(|index| self.nodes[*index].as_ref())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// When `path` was last modified, if it is in the tree and exists.
+    pub fn last_update(&self, path: &Path) -> Option<SystemTime> {
+        self.find(path).and_then(|id| self.point(id).last_update)
+    }
+
+    /// How `path` changed in the last poll.
+    pub fn event_kind(&self, path: &Path) -> EventKind {
+        return match self.find(path) {
+            Some(id) if self.created.contains(&id) => EventKind::Created,
+            Some(id) if self.point(id).last_update.is_some() => EventKind::Modified,
+            _ => EventKind::Removed,
+        };
+    }
+
+    /// Groups changed paths by the index of the config nodes owning them. A
+    /// path owned by several nodes is routed to each of them, one owned by
+    /// none is dropped.
+    pub fn route(&self, paths: &[PathBuf]) -> BTreeMap<usize, Vec<PathBuf>> {
+        let mut routes: BTreeMap<usize, Vec<PathBuf>> = BTreeMap::new();
+        for path in paths {
+            let Some(id) = self.find(path) else {
+                continue;
+            };
+            for index in &self.point(id).conf_node {
+                routes.entry(*index).or_default().push(path.clone());
+            }
+        }
+        routes
+    }
+
+    /// When `path` was last modified, `None` if it is gone, including when
+    /// it is removed while being stated.
+    fn last_modified(path: &Path) -> Option<SystemTime> {
+        return fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+    }
+
+    /// Stats `ids` in parallel, the tree being untouched until they all return.
+    fn stat_points(&self, ids: &[PointId]) -> Vec<(PointId, Option<SystemTime>)> {
+        ids.par_iter()
+            .map(|id| (*id, Self::last_modified(&self.full_path(*id))))
+            .collect()
+    }
+
+    fn init_points(&mut self, ids: &[PointId]) {
+        let is_dir = ids
+            .par_iter()
+            .map(|id| self.full_path(*id).is_dir())
+            .collect::<Vec<_>>();
+        for ((id, t_time), is_dir) in self.stat_points(ids).into_iter().zip(is_dir) {
+            let point = self.point_mut(id);
+            point.last_update = t_time;
+            point.is_dir = is_dir;
+        }
+    }
+
+    fn update_point(&mut self, id: PointId, t_time: Option<SystemTime>) -> bool {
+        let point = self.point_mut(id);
+        match t_time {
+            Some(_) if point.last_update.lt(&t_time) => {
+                point.last_update = t_time;
                 return true;
             }
-        } else {
-            if self.last_update.ne(&Box::new(None)) {
-                self.last_update = None;
+            None if point.last_update.is_some() => {
+                point.last_update = None;
                 return true;
             }
+            _ => (),
         }
-        println!("No Update for {}", self.node.display());
+        trace!("No Update for {}", self.node(id).to_string_lossy());
         return false;
     }
 
-    pub fn poll_branches(&mut self) -> Vec<PathBuf> {
-        trace!("Polling Branches of {}", self.node.display());
+    pub fn poll_point(&mut self, id: PointId) -> bool {
+        let t_time = Self::last_modified(&self.full_path(id));
+        return self.update_point(id, t_time);
+    }
+
+    /// Polls every point in `ids` and returns the ones that changed.
+    pub fn poll_points(&mut self, ids: &[PointId]) -> Vec<PointId> {
+        self.stat_points(ids)
+            .into_iter()
+            .filter(|(id, t_time)| self.update_point(*id, *t_time))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    pub fn poll_branches(&mut self, id: PointId) -> Vec<PathBuf> {
+        trace!("Polling Branches of {}", self.node(id).to_string_lossy());
         let mut update: Vec<PathBuf> = Vec::new();
-        if self.node == PathBuf::from("WinRoot")
-            || self.node == PathBuf::from("/")
-            || self.poll_point()
-        {
-            if !self.branches.is_empty() {
-                self.branches.iter_mut().for_each(|b| {
-                    let paths = b.poll_branches();
-                    update = paths;
+        let node = self.node(id);
+        if node == "WinRoot" || node == "/" || self.poll_point(id) {
+            let branches = self.branches(id).collect::<Vec<_>>();
+            if !branches.is_empty() {
+                branches.into_iter().for_each(|b| {
+                    update.append(&mut self.poll_branches(b));
                 })
             } else {
-                update.push(self.full_path.clone());
+                update.push(self.full_path(id));
             }
-        } else {
-            update = Vec::new();
         }
         trace!("Update Val {:#?}", update);
         update
     }
+
+    /// Drops the points a previous poll found missing. They are kept until
+    /// then so the change reported for them can still be routed to their
+    /// owners, which makes this the first thing a poll does.
+    pub fn prune(&mut self) {
+        let missing = self
+            .ids()
+            .filter(|id| *id != ROOT && self.point(*id).last_update.is_none())
+            .collect::<Vec<_>>();
+        for id in missing {
+            if self.points[id].is_some() {
+                self.remove(id);
+            }
+        }
+    }
+
+    /// Polls the directories of the tree for structural changes.
+    ///
+    /// Adding or removing an entry bumps a directory's mtime while editing a
+    /// file does not, so only directories whose mtime moved are re-read to
+    /// pick up new matches instead of re-globbing the whole tree. Content
+    /// changes are left to stat-ing the file leaves, and anything found
+    /// missing by either is dropped by the next [`Treemap::prune`]. Points
+    /// above a node's root are never stat-ed.
+    pub fn poll_structure(&mut self) -> Vec<PathBuf> {
+        self.created.clear();
+        let mut dirs = Vec::new();
+        let mut pending = vec![(ROOT, Vec::new())];
+        while let Some((id, mut scope)) = pending.pop() {
+            let point = self.point(id);
+            if !point.is_dir {
+                continue;
+            }
+            scope.extend(point.scope.iter().copied());
+            pending.extend(
+                point
+                    .branches
+                    .iter()
+                    .map(|(_, branch)| (*branch, scope.clone())),
+            );
+            if !scope.is_empty() {
+                dirs.push((id, scope));
+            }
+        }
+
+        let ids = dirs
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| self.points[*id].is_some())
+            .collect::<Vec<_>>();
+        let changed = self.poll_points(&ids);
+        let mut update = Vec::new();
+        for id in changed {
+            let scope = dirs
+                .iter()
+                .find(|(dir, _)| *dir == id)
+                .map(|(_, scope)| scope.clone())
+                .unwrap_or_default();
+            update.append(&mut self.rescan(id, &scope));
+        }
+        update
+    }
+
+    /// Re-reads a directory after its mtime changed, adding any new entries
+    /// matched by the nodes in `scope` and returning the new files.
+    fn rescan(&mut self, id: PointId, scope: &[usize]) -> Vec<PathBuf> {
+        let full_path = self.full_path(id);
+        let Ok(entries) = fs::read_dir(&full_path) else {
+            return Vec::new();
+        };
+        let entries = entries
+            .flatten()
+            .filter(|entry| self.branch(id, &entry.file_name()).is_none())
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        let mut update = BTreeSet::new();
+        for index in scope {
+            let conf_node = self.nodes[*index].clone();
+            if conf_node.path_pattern.is_none() {
+                if conf_node.matches(&full_path) {
+                    update.insert(full_path.clone());
+                }
+                continue;
+            }
+            for path in &entries {
+                let mut files = Vec::new();
+                if conf_node.matches(path) {
+                    files.push(path.clone());
+                }
+                if path.is_dir() && conf_node.tracks_dir(path) {
+                    let (mut inner_files, dirs) = conf_node.scan(path);
+                    files.append(&mut inner_files);
+                    self.insert(path);
+                    dirs.iter().for_each(|dir| {
+                        self.insert(dir);
+                    });
+                }
+                for file in files {
+                    if let Some(file_id) = self.insert(&file) {
+                        self.link_conf_node(file_id, *index);
+                        self.created.insert(file_id);
+                    }
+                    update.insert(file);
+                }
+            }
+        }
+        update.into_iter().collect()
+    }
+
+    /// The nodes at `indices`, sorted and without duplicates, so trees built
+    /// from nodes in another order compare equal.
+    fn distinct_nodes(&self, indices: &[usize]) -> Vec<&Node> {
+        let mut nodes = indices
+            .iter()
+            .map(|index| self.nodes[*index].as_ref())
+            .collect::<Vec<_>>();
+        nodes.sort();
+        nodes.dedup();
+        return nodes;
+    }
+
+    fn eq_point(&self, id: PointId, other: &Self, other_id: PointId) -> bool {
+        let (a, b) = (self.point(id), other.point(other_id));
+        if self.node(id) != other.node(other_id) {
+            return false;
+        }
+
+        if a.last_update != b.last_update {
+            return false;
+        }
+
+        if a.branches.len() != b.branches.len() {
+            return false;
+        }
+
+        for branch in self.branches(id) {
+            match other.branch(other_id, self.node(branch)) {
+                Some(other_branch) if self.eq_point(branch, other, other_branch) => (),
+                _ => return false,
+            }
+        }
+
+        if self.distinct_nodes(&a.conf_node) != other.distinct_nodes(&b.conf_node) {
+            return false;
+        }
+
+        return true;
+    }
+
+    /// Draws the tree with box-drawing lines, one point per line.
+    ///
+    /// Chains of directories with a single branch and no owners are collapsed
+    /// into one line, directories show how many files are below them and
+    /// points show the labels of the nodes owning them. Points in `polled`
+    /// are marked with a `*`.
+    pub fn render(&self, polled: &BTreeSet<PointId>) -> String {
+        let mut out = String::new();
+        let _ = self.render_point(ROOT, polled, None, &mut out);
+        out
+    }
+
+    fn render_point(
+        &self,
+        id: PointId,
+        polled: &BTreeSet<PointId>,
+        indent: Option<(&str, bool)>,
+        out: &mut String,
+    ) -> std::fmt::Result {
+        let mut id = id;
+        let mut label = PathBuf::new();
+        loop {
+            let segment = self.node(id);
+            if !(cfg!(windows) && segment == "WinRoot") {
+                label.push(segment);
+            }
+            let point = self.point(id);
+            let collapses =
+                point.branches.len() == 1 && point.conf_node.is_empty() && !polled.contains(&id);
+            match point.branches.first() {
+                Some((_, branch)) if collapses && self.branches(*branch).len() > 0 => {
+                    id = *branch;
+                }
+                _ => break,
+            }
+        }
+
+        let prefix = match indent {
+            Some((prefix, is_last)) => {
+                write!(out, "{prefix}{}", if is_last { "└── " } else { "├── " })?;
+                format!("{prefix}{}", if is_last { "    " } else { "│   " })
+            }
+            None => String::new(),
+        };
+        write!(out, "{}", label.display())?;
+        let point = self.point(id);
+        if !point.branches.is_empty() || point.is_dir {
+            match self.count_files(id) {
+                1 => write!(out, " (1 file)")?,
+                count => write!(out, " ({count} files)")?,
+            }
+        }
+        if !point.conf_node.is_empty() {
+            let labels = point
+                .conf_node
+                .iter()
+                .map(|index| self.nodes[*index].label())
+                .collect::<Vec<_>>();
+            write!(out, " [{}]", labels.join(", "))?;
+        }
+        if polled.contains(&id) {
+            write!(out, " *")?;
+        }
+        writeln!(out)?;
+
+        let mut branches = self.branches(id).collect::<Vec<_>>();
+        branches.sort_by(|a, b| self.node(*a).cmp(self.node(*b)));
+        for (index, branch) in branches.iter().enumerate() {
+            let is_last = index + 1 == branches.len();
+            self.render_point(*branch, polled, Some((&prefix, is_last)), out)?;
+        }
+        Ok(())
+    }
+
+    /// How many files are below `id`, not counting empty directories.
+    fn count_files(&self, id: PointId) -> usize {
+        let point = self.point(id);
+        if point.branches.is_empty() {
+            return usize::from(!point.is_dir);
+        }
+        return self
+            .branches(id)
+            .map(|branch| self.count_files(branch))
+            .sum();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+    use std::{env::temp_dir, thread::sleep, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn test_treemap_merge_shared_prefix() {
+        let mut a = Treemap::new(
+            PathBuf::from("/"),
+            vec![Treemap::new(
+                PathBuf::from("home"),
+                vec![Treemap::new(
+                    PathBuf::from("bob_ross"),
+                    Vec::new(),
+                    PathBuf::from("/home"),
+                )],
+                PathBuf::from("/"),
+            )],
+            PathBuf::new(),
+        );
+        let b = Treemap::new(
+            PathBuf::from("/"),
+            vec![Treemap::new(
+                PathBuf::from("home"),
+                vec![Treemap::new(
+                    PathBuf::from("steve_ross"),
+                    Vec::new(),
+                    PathBuf::from("/home"),
+                )],
+                PathBuf::from("/"),
+            )],
+            PathBuf::new(),
+        );
+        a.merge(b);
+
+        let mut c = Treemap::new(PathBuf::from("/"), Vec::new(), PathBuf::new());
+        c.insert(Path::new("/home/steve_ross"));
+        c.insert(Path::new("/home/bob_ross"));
+        assert_eq!(a, c);
+        let home = a.branch(ROOT, OsStr::new("home")).unwrap();
+        assert_eq!(a.branches(home).len(), 2);
+    }
+
+    #[test]
+    fn test_treemap_remove_reuses_points() {
+        let mut map = Treemap::from_paths(&[
+            PathBuf::from("/home/bob_ross/the old mill.png"),
+            PathBuf::from("/home/bob_ross/mountain retreat.png"),
+        ]);
+        assert_eq!(map.len(), 5);
+        let bob_ross = map.find(Path::new("/home/bob_ross")).unwrap();
+        assert_eq!(
+            map.full_path(map.branches(bob_ross).next().unwrap())
+                .parent(),
+            Some(Path::new("/home/bob_ross"))
+        );
+
+        map.remove(bob_ross);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.find(Path::new("/home/bob_ross")), None);
+
+        map.insert(Path::new("/home/steve_ross"));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.points.len(), 5);
+    }
+
+    #[test]
+    #[serial]
+    fn test_treemap_route_overlapping_nodes() {
+        let dir = temp_dir().join("treemap_route_test1");
+        let _ = fs::create_dir_all(&dir);
+        let png = dir.join("the old mill.png");
+        let txt = dir.join("notes.txt");
+        fs::File::create(&png).unwrap();
+        fs::File::create(&txt).unwrap();
+        let images = Box::from(Node {
+            root: dir.clone(),
+            path_pattern: Some(String::from("*.png")),
+            exec: None,
+            ..Default::default()
+        });
+        let all = Box::from(Node {
+            root: dir.clone(),
+            path_pattern: Some(String::from("*")),
+            exec: None,
+            ..Default::default()
+        });
+        let map = Treemap::from_nodes(&[images.clone(), all.clone()]);
+
+        let mut expected = all.build_treemap();
+        expected.merge(images.build_treemap());
+        assert_eq!(map, expected);
+        assert_eq!(map.branches(map.find(&dir).unwrap()).len(), 2);
+        assert_eq!(
+            map.route(&[png.clone(), txt.clone()]),
+            BTreeMap::from([(0, vec![png.clone()]), (1, vec![png.clone(), txt])])
+        );
+        // Identical nodes are still told apart by their place in the config.
+        let map = Treemap::from_nodes(&[images.clone(), images]);
+        assert_eq!(
+            map.route(std::slice::from_ref(&png)),
+            BTreeMap::from([(0, vec![png.clone()]), (1, vec![png])])
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_treemap_route_new_file() {
+        let dir = temp_dir().join("treemap_route_test2");
+        let _ = fs::create_dir_all(&dir);
+        let images = Box::from(Node {
+            root: dir.clone(),
+            path_pattern: Some(String::from("*.png")),
+            exec: None,
+            ..Default::default()
+        });
+        let notes = Box::from(Node {
+            root: dir.clone(),
+            path_pattern: Some(String::from("*.txt")),
+            exec: None,
+            ..Default::default()
+        });
+        let mut map = Treemap::from_nodes(&[images.clone(), notes]);
+        sleep(Duration::from_millis(500));
+        let png = dir.join("mountain retreat.png");
+        fs::File::create(&png).unwrap();
+
+        let changed = map.poll_structure();
+        assert_eq!(changed, vec![png.clone()]);
+        assert_eq!(map.event_kind(&png), EventKind::Created);
+        assert_eq!(map.route(&changed), BTreeMap::from([(0, vec![png])]));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[cfg(not(target_family = "windows"))]
+    fn test_treemap_render() {
+        let mill = PathBuf::from("/home/bob_ross/paintings/the old mill.png");
+        let trees = PathBuf::from("/home/bob_ross/notes/2024/happy trees.txt");
+        let mut map = Treemap::from_paths(&[
+            mill.clone(),
+            PathBuf::from("/home/bob_ross/paintings/mountain retreat.png"),
+            trees.clone(),
+        ]);
+        let index = map.add_conf_node(Box::from(Node {
+            name: Some(String::from("bob_ross")),
+            ..Default::default()
+        }));
+        for path in [&mill, &trees] {
+            let id = map.find(path).unwrap();
+            map.link_conf_node(id, index);
+        }
+        let paintings = map.find(Path::new("/home/bob_ross/paintings")).unwrap();
+        assert_eq!(
+            map.render(&BTreeSet::from([paintings])),
+            "/home/bob_ross (3 files)
+├── notes/2024 (1 file)
+│   └── happy trees.txt [bob_ross]
+└── paintings (2 files) *
+    ├── mountain retreat.png
+    └── the old mill.png [bob_ross]
+"
+        );
+        assert_eq!(map.to_string(), map.render(&BTreeSet::new()));
+    }
 }
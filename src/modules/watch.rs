@@ -0,0 +1,785 @@
+use log::{debug, error, info, trace, warn};
+use normpath::PathExt;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    command::Change,
+    config::Config,
+    control::{self, Control, Request, Response},
+    events::{self, Event},
+    keys::{Key, Keys},
+    node::Node,
+    output,
+    scheduler::Scheduler,
+    signals,
+    snapshot::{self, Snapshot},
+    treemap::Treemap,
+};
+
+/// The runs a poll's changed paths trigger, by node index.
+pub fn triggered(map: &Treemap, paths: &[PathBuf]) -> BTreeMap<usize, Vec<Change>> {
+    map.route(paths)
+        .into_iter()
+        .map(|(index, paths)| {
+            let changes = paths
+                .into_iter()
+                .map(|path| Change {
+                    kind: map.event_kind(&path),
+                    path,
+                })
+                .collect();
+            (index, changes)
+        })
+        .collect()
+}
+
+/// Drops the changes a node is not to be triggered by: those to its outputs
+/// and, if it suppresses its own changes, those made while it ran.
+pub fn suppress(
+    config: &Config,
+    scheduler: &Scheduler,
+    map: &Treemap,
+    triggered: BTreeMap<usize, Vec<Change>>,
+) -> BTreeMap<usize, Vec<Change>> {
+    let now = SystemTime::now();
+    triggered
+        .into_iter()
+        .filter_map(|(index, changes)| {
+            let conf_node = &config.nodes[index];
+            let changes = changes
+                .into_iter()
+                .filter(|change| {
+                    let time = map.last_update(&change.path).unwrap_or(now);
+                    let suppressed = conf_node.is_output(&change.path)
+                        || (conf_node.suppress_own_changes && scheduler.ran_at(index, time));
+                    if suppressed {
+                        trace!(
+                            "Suppressed {} for {}",
+                            change.path.display(),
+                            conf_node.label()
+                        );
+                    }
+                    !suppressed
+                })
+                .collect::<Vec<_>>();
+            (!changes.is_empty()).then_some((index, changes))
+        })
+        .collect()
+}
+
+/// Notices nodes triggering themselves over and over, eg. a formatter
+/// rewriting the files it watches.
+#[derive(Debug)]
+pub struct LoopGuard {
+    /// Runs in a row of each node triggered by changes made while it ran.
+    streaks: Vec<u32>,
+}
+
+impl LoopGuard {
+    pub fn new(len: usize) -> Self {
+        return Self {
+            streaks: vec![0; len],
+        };
+    }
+
+    /// Drops the changes nodes made themselves while they ran once they were
+    /// triggered by their own changes more than their `max_retriggers` times
+    /// in a row. Other changes always go through, and a run triggered by
+    /// them alone ends the streak.
+    pub fn check(
+        &mut self,
+        config: &Config,
+        scheduler: &Scheduler,
+        map: &Treemap,
+        triggered: BTreeMap<usize, Vec<Change>>,
+    ) -> BTreeMap<usize, Vec<Change>> {
+        let now = SystemTime::now();
+        triggered
+            .into_iter()
+            .filter_map(|(index, changes)| {
+                let (own, others): (Vec<_>, Vec<_>) = changes.into_iter().partition(|change| {
+                    scheduler.ran_at(index, map.last_update(&change.path).unwrap_or(now))
+                });
+                if own.is_empty() {
+                    self.streaks[index] = 0;
+                    return Some((index, others));
+                }
+                self.streaks[index] += 1;
+                let streak = self.streaks[index];
+                let conf_node = &config.nodes[index];
+                let changes = match conf_node.max_retriggers {
+                    Some(max) if streak > max => {
+                        if streak == max + 1 {
+                            warn!(
+                                "{} Triggered Itself {max} Times in a Row, Skipping its Own Changes",
+                                conf_node.label()
+                            );
+                        }
+                        others
+                    }
+                    _ => own.into_iter().chain(others).collect(),
+                };
+                (!changes.is_empty()).then_some((index, changes))
+            })
+            .collect()
+    }
+}
+
+/// Runs of the nodes `filter` picks, without any changes.
+fn every(config: &Config, filter: impl Fn(&Node) -> bool) -> BTreeMap<usize, Vec<Change>> {
+    config
+        .nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, conf_node)| filter(conf_node))
+        .map(|(index, _)| (index, Vec::new()))
+        .collect()
+}
+
+/// Stops or goes back to reacting to changes. What changed while paused is
+/// caught up on without running anything.
+fn set_paused(config: &Config, map: &mut Treemap, paused: &mut bool, pause: bool) {
+    if *paused == pause {
+        return;
+    }
+    *paused = pause;
+    match pause {
+        true => info!("Paused Watching"),
+        false => {
+            config.mode.poll(map);
+            info!("Resumed Watching");
+        }
+    }
+}
+
+/// Answers a request from the control socket.
+fn respond(
+    config: &Config,
+    scheduler: &Scheduler,
+    map: &mut Treemap,
+    paused: &mut bool,
+    request: Request,
+) -> Response {
+    return match request {
+        Request::List => Response::Nodes {
+            nodes: control::list(config, scheduler),
+        },
+        Request::Trigger { node } => {
+            match config
+                .nodes
+                .iter()
+                .position(|conf_node| conf_node.label() == node)
+            {
+                Some(index) => {
+                    scheduler.schedule(index, Vec::new());
+                    Response::Ok
+                }
+                None => Response::Error {
+                    message: format!("There is no Node {node}"),
+                },
+            }
+        }
+        Request::Pause => {
+            set_paused(config, map, paused, true);
+            Response::Ok
+        }
+        Request::Resume => {
+            set_paused(config, map, paused, false);
+            Response::Ok
+        }
+        Request::Tree => Response::Tree {
+            paths: control::tree(map),
+        },
+        Request::Subscribe => Response::Subscribed,
+    };
+}
+
+/// Queues the runs of triggered nodes, telling `--json` and subscribers what
+/// triggered them.
+fn schedule(config: &Config, scheduler: &Scheduler, triggered: BTreeMap<usize, Vec<Change>>) {
+    for (index, changes) in &triggered {
+        events::emit(&Event::Changed {
+            node: config.nodes[*index].label(),
+            changes: changes.clone(),
+        });
+    }
+    scheduler.schedule_all(triggered);
+}
+
+/// What changed since the snapshot in `state_file` was saved, by the nodes it
+/// triggers. Nothing did if there is no snapshot yet.
+fn missed(config: &Config, map: &Treemap, state_file: &Path) -> BTreeMap<usize, Vec<Change>> {
+    if !state_file.exists() {
+        info!("No Snapshot in {} Yet", state_file.display());
+        return BTreeMap::new();
+    }
+    let Ok(snapshot) = Snapshot::load(state_file) else {
+        return BTreeMap::new();
+    };
+    let changes = snapshot.diff(&Snapshot::take(map, Some(state_file)));
+    for change in &changes {
+        debug!(
+            "{:?} while Not Watching: {}",
+            change.kind,
+            change.path.display()
+        );
+    }
+    let triggered = snapshot::route(config, &changes);
+    info!(
+        "{} Files of {} Nodes Changed while Not Watching",
+        changes.len(),
+        triggered.len()
+    );
+    return triggered;
+}
+
+/// Polls the config's nodes and runs the ones owning what changed, until every
+/// node has stopped watching, `q` is pressed or irminsul got a signal to stop.
+///
+/// With a `state_file`, what changed since the last session is looked up
+/// first and run with `catch_up`, and the files are saved to it on the way
+/// out.
+pub fn watch(config: &Config) -> Result<(), ()> {
+    let mut map = config.build_treemap();
+    let missed = match &config.state_file {
+        Some(state_file) => missed(config, &map, state_file),
+        None => BTreeMap::new(),
+    };
+    let result = watch_map(config, &mut map, missed);
+    if let Some(state_file) = &config.state_file {
+        let _ = Snapshot::take(&map, Some(state_file)).save(state_file);
+    }
+    return result;
+}
+
+fn watch_map(
+    config: &Config,
+    map: &mut Treemap,
+    missed: BTreeMap<usize, Vec<Change>>,
+) -> Result<(), ()> {
+    let scheduler = Scheduler::new(config)?;
+    let keys = Keys::listen();
+    let control = config.socket.as_deref().map(Control::listen).transpose()?;
+    let mut guard = LoopGuard::new(config.nodes.len());
+    info!("Watching {} Nodes", config.nodes.len());
+    scheduler.schedule_all(every(config, |conf_node| conf_node.run_on_start));
+    if config.catch_up {
+        schedule(config, &scheduler, missed);
+    }
+    let mut paused = false;
+    while scheduler.is_watching() {
+        if let Some(signal) = signals::received() {
+            info!("Stopping on Signal {signal}");
+            return Err(());
+        }
+        match keys.next(config.mode.poll_rate()) {
+            Some(Key::RerunAll) => scheduler.schedule_all(every(config, |_| true)),
+            Some(Key::Rerun(index)) if index < config.nodes.len() => {
+                scheduler.schedule(index, Vec::new())
+            }
+            Some(Key::Rerun(index)) => warn!("There is no Node {}", index + 1),
+            Some(Key::Pause) => {
+                let pause = !paused;
+                set_paused(config, map, &mut paused, pause);
+            }
+            Some(Key::Clear) => output::clear_screen(),
+            Some(Key::Quit) => {
+                info!("Quitting");
+                return Ok(());
+            }
+            None => (),
+        }
+        while let Some((request, reply)) = control.as_ref().and_then(Control::next) {
+            let _ = reply.send(respond(config, &scheduler, map, &mut paused, request));
+        }
+        if paused {
+            continue;
+        }
+        let paths = config.mode.poll(map).unwrap_or_default();
+        let triggered = suppress(config, &scheduler, map, triggered(map, &paths));
+        schedule(
+            config,
+            &scheduler,
+            guard.check(config, &scheduler, map, triggered),
+        );
+    }
+    error!("Every Node Stopped Watching");
+    return Err(());
+}
+
+/// Runs the named nodes once, along with their dependents, and waits for them
+/// including long running steps.
+pub fn run(config: &Config, names: &[String]) -> Result<(), ()> {
+    let mut triggered = BTreeMap::new();
+    for name in names {
+        let Some(index) = config.position(name) else {
+            error!("No Node is Named {name}");
+            return Err(());
+        };
+        triggered.insert(index, Vec::new());
+    }
+    let scheduler = Scheduler::new(config)?;
+    scheduler.schedule_all(triggered);
+    scheduler.wait();
+    scheduler.wait_services();
+    let failed = (0..config.nodes.len()).any(|index| scheduler.result(index) == Some(Err(())));
+    return match failed {
+        true => Err(()),
+        false => Ok(()),
+    };
+}
+
+/// Prints the tree the config's nodes watch, marking the points the mode
+/// polls first.
+pub fn tree(config: &Config) -> Result<(), ()> {
+    let map = config.build_treemap();
+    let polled = config.mode.polled_points(&map);
+    print!("{}", map.render(&polled));
+    if !polled.is_empty() {
+        println!("\n* Polled on every pass, the rest only once these change");
+    }
+    return Ok(());
+}
+
+/// Prints why each node does or does not match `path`, whether it is in the
+/// tree and what would run on a change to it. Fails if no node matches.
+pub fn explain(config: &Config, path: &Path) -> Result<(), ()> {
+    let path = path
+        .normalize()
+        .map(|path| path.into_path_buf())
+        .or_else(|_| std::path::absolute(path))
+        .map_err(|err| error!("Failed to Resolve {}: {err}", path.display()))?;
+    let map = config.build_treemap();
+    let owners = map
+        .owners(&path)
+        .iter()
+        .map(|conf_node| conf_node.label())
+        .collect::<Vec<_>>();
+    match (map.find(&path), owners.as_slice()) {
+        (None, _) => println!("{} is not in the tree", path.display()),
+        (Some(_), []) => println!("{} is in the tree, owned by no node", path.display()),
+        (Some(_), owners) => println!(
+            "{} is in the tree, owned by {}",
+            path.display(),
+            owners.join(", ")
+        ),
+    }
+    let change = Change {
+        kind: map.event_kind(&path),
+        path: path.clone(),
+    };
+    let mut matched = false;
+    for conf_node in &config.nodes {
+        let label = conf_node.label();
+        let verdict = conf_node.explain(&path);
+        println!("{label}: {verdict}");
+        if path.is_dir() && conf_node.tracks_dir(&path) {
+            println!("  new entries below it are picked up");
+        }
+        if !verdict.is_match() {
+            continue;
+        }
+        matched = true;
+        if conf_node.is_output(&path) {
+            println!("  it is an output, so changes to it never trigger {label}");
+            continue;
+        }
+        for command in conf_node.commands(std::slice::from_ref(&change)) {
+            println!("  runs {command}");
+        }
+    }
+    return match matched {
+        true => Ok(()),
+        false => Err(()),
+    };
+}
+
+/// Prints the files changed since the snapshot in the config's `state_file`,
+/// or since the Unix timestamp `since`, and saves a new snapshot. Returns
+/// whether any node's files changed, like a stale target in make, failing only
+/// when there is nothing to compare against or the scan itself fails.
+pub fn changed(config: &Config, since: Option<u64>) -> Result<bool, ()> {
+    for conf_node in &config.nodes {
+        let root = conf_node.normalized_root();
+        if !root.exists() {
+            error!("{}: Root {} is Missing", conf_node.label(), root.display());
+            return Err(());
+        }
+    }
+    let map = config.build_treemap();
+    let state_file = config.state_file.as_deref();
+    let current = Snapshot::take(&map, state_file);
+    let changes = match (since, state_file) {
+        (Some(since), _) => current.modified_since(since_epoch(since)),
+        (None, Some(state_file)) if state_file.exists() => {
+            let snapshot = Snapshot::load(state_file)?;
+            if let Ok(elapsed) = snapshot.taken().elapsed() {
+                debug!("Comparing against a Snapshot from {elapsed:.0?} Ago");
+            }
+            snapshot.diff(&current)
+        }
+        (None, Some(state_file)) => {
+            info!(
+                "No Snapshot in {} Yet, Every File Counts as Created",
+                state_file.display()
+            );
+            Snapshot::default().diff(&current)
+        }
+        (None, None) => {
+            error!("Missing a state_file or --since to Compare Against");
+            return Err(());
+        }
+    };
+    if let Some(state_file) = state_file {
+        current.save(state_file)?;
+    }
+    return Ok(report(config, &changes));
+}
+
+/// The time `seconds` after the epoch, or now for times too far out to
+/// represent.
+fn since_epoch(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH
+        .checked_add(Duration::from_secs(seconds))
+        .unwrap_or_else(SystemTime::now)
+}
+
+/// Prints `changes`, as events with `--json`, returning whether they trigger
+/// any node.
+fn report(config: &Config, changes: &[Change]) -> bool {
+    let triggered = snapshot::route(config, changes);
+    match events::is_enabled() {
+        true => {
+            for (index, changes) in &triggered {
+                events::emit(&Event::Changed {
+                    node: config.nodes[*index].label(),
+                    changes: changes.clone(),
+                });
+            }
+        }
+        false => {
+            for change in changes {
+                println!("{} {}", change.kind, change.path.display());
+            }
+        }
+    }
+    return !triggered.is_empty();
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use serial_test::serial;
+    use std::{env::temp_dir, fs, thread::sleep, time::Duration};
+
+    use super::*;
+    use crate::modules::{command::EventKind, node::OnFailure};
+
+    #[test]
+    #[serial]
+    fn test_watch_triggered() {
+        let dir = temp_dir().join("watch_test1");
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::create_dir_all(&dir);
+        let png = dir.join("the old mill.png");
+        fs::File::create(&png).unwrap();
+        let config = Config {
+            nodes: vec![
+                Box::from(Node {
+                    name: Some(String::from("notes")),
+                    root: dir.clone(),
+                    path_pattern: Some(String::from("*.txt")),
+                    ..Default::default()
+                }),
+                Box::from(Node {
+                    name: Some(String::from("images")),
+                    root: dir.clone(),
+                    path_pattern: Some(String::from("*.png")),
+                    ..Default::default()
+                }),
+            ],
+            ..Default::default()
+        };
+        let mut map = config.build_treemap();
+        sleep(Duration::from_millis(500));
+        let txt = dir.join("notes.txt");
+        fs::File::create(&txt).unwrap();
+        fs::remove_file(&png).unwrap();
+
+        let paths = config.mode.poll(&mut map).unwrap();
+        assert_eq!(
+            triggered(&map, &paths),
+            BTreeMap::from([
+                (
+                    0,
+                    vec![Change {
+                        path: txt,
+                        kind: EventKind::Created
+                    }]
+                ),
+                (
+                    1,
+                    vec![Change {
+                        path: png,
+                        kind: EventKind::Removed
+                    }]
+                ),
+            ])
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_watch_run() {
+        let out = temp_dir().join("watch_test2.txt");
+        let _ = fs::remove_file(&out);
+        let config = Config {
+            nodes: vec![
+                Box::from(Node {
+                    name: Some(String::from("proto")),
+                    root: temp_dir(),
+                    exec: Some(format!("echo proto >> {}", out.display())),
+                    ..Default::default()
+                }),
+                Box::from(Node {
+                    name: Some(String::from("server")),
+                    root: temp_dir(),
+                    exec: Some(format!("echo server >> {}", out.display())),
+                    depends_on: vec![String::from("proto")],
+                    ..Default::default()
+                }),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(run(&config, &[String::from("server")]), Ok(()));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "server\n");
+        assert_eq!(run(&config, &[String::from("proto")]), Ok(()));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "server\nproto\nserver\n");
+        assert_eq!(run(&config, &[String::from("missing")]), Err(()));
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_watch_run_on_start() {
+        let out = temp_dir().join("watch_test3.txt");
+        let _ = fs::remove_file(&out);
+        let config = Config {
+            nodes: vec![Box::from(Node {
+                root: temp_dir(),
+                exec: Some(format!("echo started >> {}; false", out.display())),
+                on_failure: OnFailure::StopWatching,
+                run_on_start: true,
+                ..Default::default()
+            })],
+            ..Default::default()
+        };
+        assert_eq!(watch(&config), Err(()));
+        assert_eq!(fs::read_to_string(&out).unwrap(), "started\n");
+        let _ = fs::remove_file(out);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_watch_suppress() {
+        let dir = temp_dir().join("watch_test4");
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::create_dir_all(dir.join("gen"));
+        let config = Config {
+            nodes: vec![Box::from(Node {
+                root: dir.clone(),
+                path_pattern: Some(String::from("**/*.rs")),
+                exec: Some(String::from("sleep 0.5")),
+                outputs: vec![String::from("gen/*.rs")],
+                suppress_own_changes: true,
+                ..Default::default()
+            })],
+            ..Default::default()
+        };
+        let mut map = config.build_treemap();
+        let scheduler = Scheduler::new(&config).unwrap();
+        sleep(Duration::from_millis(500));
+        scheduler.schedule(0, Vec::new());
+        sleep(Duration::from_millis(200));
+        fs::File::create(dir.join("during.rs")).unwrap();
+        scheduler.wait();
+        fs::File::create(dir.join("gen").join("out.rs")).unwrap();
+        // File times come from a coarser clock, so they can lag behind.
+        sleep(Duration::from_millis(50));
+        let after = dir.join("after.rs");
+        fs::File::create(&after).unwrap();
+
+        let paths = config.mode.poll(&mut map).unwrap();
+        assert_eq!(paths.len(), 3);
+        assert_eq!(
+            suppress(&config, &scheduler, &map, triggered(&map, &paths)),
+            BTreeMap::from([(
+                0,
+                vec![Change {
+                    path: after,
+                    kind: EventKind::Created
+                }]
+            )])
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_watch_missed() {
+        let dir = temp_dir().join("watch_test5");
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::create_dir_all(&dir);
+        let kept = dir.join("kept.rs");
+        let edited = dir.join("edited.rs");
+        let removed = dir.join("removed.rs");
+        for path in [&kept, &edited, &removed] {
+            fs::File::create(path).unwrap();
+        }
+        let state_file = dir.join("state.json");
+        let config = Config {
+            state_file: Some(state_file.clone()),
+            nodes: vec![Box::from(Node {
+                root: dir.clone(),
+                path_pattern: Some(String::from("*")),
+                ..Default::default()
+            })],
+            ..Default::default()
+        };
+        let map = config.build_treemap();
+        assert_eq!(missed(&config, &map, &state_file), BTreeMap::new());
+        Snapshot::take(&map, Some(&state_file))
+            .save(&state_file)
+            .unwrap();
+
+        sleep(Duration::from_millis(50));
+        fs::write(&edited, "fn main() {}").unwrap();
+        fs::remove_file(&removed).unwrap();
+        let created = dir.join("created.rs");
+        fs::File::create(&created).unwrap();
+        let map = config.build_treemap();
+        assert_eq!(
+            missed(&config, &map, &state_file),
+            BTreeMap::from([(
+                0,
+                vec![
+                    Change {
+                        path: created,
+                        kind: EventKind::Created
+                    },
+                    Change {
+                        path: edited,
+                        kind: EventKind::Modified
+                    },
+                    Change {
+                        path: removed,
+                        kind: EventKind::Removed
+                    },
+                ]
+            )])
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    fn test_watch_changed() {
+        let dir = temp_dir().join("watch_test6");
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::create_dir_all(&dir);
+        let notes = dir.join("notes.txt");
+        fs::File::create(&notes).unwrap();
+        let mut config = Config {
+            nodes: vec![Box::from(Node {
+                root: dir.clone(),
+                path_pattern: Some(String::from("*.txt")),
+                ..Default::default()
+            })],
+            ..Default::default()
+        };
+        assert_eq!(changed(&config, None), Err(()));
+        assert_eq!(changed(&config, Some(4_000_000_000)), Ok(false));
+        assert_eq!(changed(&config, Some(0)), Ok(true));
+
+        let state_file = dir.join("state.json");
+        config.state_file = Some(state_file.clone());
+        assert_eq!(changed(&config, None), Ok(true));
+        assert!(state_file.exists());
+        assert_eq!(changed(&config, None), Ok(false));
+        fs::remove_file(&notes).unwrap();
+        assert_eq!(changed(&config, None), Ok(true));
+        assert_eq!(changed(&config, None), Ok(false));
+
+        fs::write(&state_file, "not json").unwrap();
+        assert_eq!(changed(&config, None), Err(()));
+        config.nodes[0].root = dir.join("missing");
+        assert_eq!(changed(&config, Some(0)), Err(()));
+
+        // A node without a pattern can watch a single file.
+        fs::File::create(&notes).unwrap();
+        config.nodes[0] = Box::from(Node {
+            root: notes.clone(),
+            ..Default::default()
+        });
+        config.state_file = None;
+        assert_eq!(changed(&config, Some(0)), Ok(true));
+        assert_eq!(changed(&config, Some(4_000_000_000)), Ok(false));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(not(target_family = "windows"))]
+    fn test_watch_loop_guard() {
+        let dir = temp_dir().join("watch_test7");
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::create_dir_all(&dir);
+        let notes = dir.join("notes.txt");
+        fs::File::create(&notes).unwrap();
+        let config = Config {
+            nodes: vec![Box::from(Node {
+                root: dir.clone(),
+                path_pattern: Some(String::from("*.txt")),
+                exec: Some(format!("sleep 0.05; touch {}", notes.display())),
+                max_retriggers: Some(1),
+                ..Default::default()
+            })],
+            ..Default::default()
+        };
+        let mut map = config.build_treemap();
+        let scheduler = Scheduler::new(&config).unwrap();
+        let mut guard = LoopGuard::new(1);
+        let mut run = |map: &mut Treemap| {
+            scheduler.schedule(0, Vec::new());
+            scheduler.wait();
+            let paths = config.mode.poll(map).unwrap();
+            guard.check(&config, &scheduler, map, triggered(map, &paths))
+        };
+        assert_eq!(run(&mut map).len(), 1);
+        assert_eq!(run(&mut map), BTreeMap::new());
+
+        // An edit made after the run is not the node's own and goes through.
+        sleep(Duration::from_millis(10));
+        fs::write(&notes, "happy trees").unwrap();
+        let paths = config.mode.poll(&mut map).unwrap();
+        assert_eq!(
+            guard.check(&config, &scheduler, &map, triggered(&map, &paths)),
+            BTreeMap::from([(
+                0,
+                vec![Change {
+                    path: notes,
+                    kind: EventKind::Modified
+                }]
+            )])
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
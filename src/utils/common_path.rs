@@ -32,6 +32,7 @@ pub fn get_common_path(paths: Vec<PathBuf>) -> Option<PathBuf> {
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec, clippy::redundant_closure)]
 mod tests {
     use super::*;
     #[test]
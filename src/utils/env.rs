@@ -0,0 +1,106 @@
+use log::error;
+use std::{fs, path::Path};
+
+/// Parses a `.env` file of `KEY=value` lines. Blank lines, `#` comments and a
+/// leading `export` are skipped, and values may be wrapped in single or double
+/// quotes.
+pub fn parse_env_file(path: &Path) -> Result<Vec<(String, String)>, ()> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| error!("Failed to Read Env File {}: {err}", path.display()))?;
+    let mut vars = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            error!("{}:{}: Expected KEY=value", path.display(), index + 1);
+            return Err(());
+        };
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) if value.len() > 1 && value.ends_with(quote) => {
+                &value[1..value.len() - 1]
+            }
+            _ => value.split(" #").next().unwrap_or_default().trim_end(),
+        };
+        vars.push((key.trim().to_owned(), value.to_owned()));
+    }
+    return Ok(vars);
+}
+
+/// Replaces every `${VAR}` in `value` with what `lookup` returns for it, `$$`
+/// being a literal `$`. Fails on variables `lookup` does not know.
+pub fn interpolate(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, ()> {
+    let mut interpolated = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        interpolated.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if let Some(tail) = rest.strip_prefix('$') {
+            interpolated.push('$');
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix('{') {
+            let Some(end) = tail.find('}') else {
+                error!("Unclosed ${{ in {value}");
+                return Err(());
+            };
+            let name = &tail[..end];
+            let Some(var) = lookup(name) else {
+                error!("Undefined Variable {name} in {value}");
+                return Err(());
+            };
+            interpolated.push_str(&var);
+            rest = &tail[end + 1..];
+        } else {
+            interpolated.push('$');
+        }
+    }
+    interpolated.push_str(rest);
+    return Ok(interpolated);
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use std::env::temp_dir;
+
+    use super::*;
+
+    #[test]
+    fn test_env_parse_env_file() {
+        let path = temp_dir().join("env_parse_test.env");
+        fs::write(
+            &path,
+            "# database\nexport DB_HOST=localhost\nDB_PORT = 5432 # default\n\nDB_NAME=\"bob's paintings\"\nDB_PASS='a#b'\n",
+        )
+        .unwrap();
+        assert_eq!(
+            parse_env_file(&path),
+            Ok(vec![
+                ("DB_HOST".to_owned(), "localhost".to_owned()),
+                ("DB_PORT".to_owned(), "5432".to_owned()),
+                ("DB_NAME".to_owned(), "bob's paintings".to_owned()),
+                ("DB_PASS".to_owned(), "a#b".to_owned()),
+            ])
+        );
+        fs::write(&path, "DB_HOST\n").unwrap();
+        assert_eq!(parse_env_file(&path), Err(()));
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_env_interpolate() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/bob_ross".to_owned()),
+            _ => None,
+        };
+        assert_eq!(
+            interpolate("${HOME}/bin:$PATH costs $$5", lookup),
+            Ok("/home/bob_ross/bin:$PATH costs $5".to_owned())
+        );
+        assert_eq!(interpolate("${MISSING}", lookup), Err(()));
+        assert_eq!(interpolate("${HOME", lookup), Err(()));
+    }
+}
@@ -1,3 +1,3 @@
 mod common_path;
 pub use common_path::get_common_path;
-pub mod get_last_modified;
+pub mod env;